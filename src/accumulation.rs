@@ -0,0 +1,601 @@
+use iced_wgpu::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, RenderPipeline};
+
+use crate::{
+    camera::rotate,
+    scene::{self, ShaderDataUniforms},
+};
+
+//Floats so averaging lots of samples doesn't band
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+//How far the zoom can get from an old image before it's too blurry or too small to show
+const MAX_STRETCH: f32 = 2.0;
+
+//Texture the fractal gets rendered into a sample at a time, along with what it takes to draw it
+//onto the screen. It keeps colors times their filter weights with the weights in alpha, drawing
+//it divides them back out
+pub struct Accumulation {
+    texture_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    size: [u32; 2],
+    //How much of the texture has the image in it, it's smaller than the screen while moving
+    image_size: [u32; 2],
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    //The image size and where the image goes on the screen
+    scale_buffer: Buffer,
+    pipeline: RenderPipeline,
+}
+
+impl Accumulation {
+    pub fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat, size: [u32; 2]) -> Self {
+        let module = scene::output_module(
+            device,
+            "blit.wgsl",
+            texture_format,
+            include_str!("shader/blit.wgsl"),
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let scale_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit scale"),
+            contents: bytemuck::cast_slice(&[1.0f32, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        //Smooth upscaling for the low resolution frames
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let texture_view = create_texture(device, size);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &scale_buffer,
+            &texture_view,
+            &sampler,
+        );
+        Self {
+            texture_view,
+            sampler,
+            size,
+            image_size: size,
+            bind_group_layout,
+            bind_group,
+            scale_buffer,
+            pipeline,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: [u32; 2]) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.texture_view = create_texture(device, size);
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.scale_buffer,
+            &self.texture_view,
+            &self.sampler,
+        );
+    }
+
+    pub fn texture_view(&self) -> &wgpu::TextureView {
+        &self.texture_view
+    }
+
+    //Only the top left `size` pixels have the image in them
+    pub fn set_image_size(&mut self, size: [u32; 2]) {
+        self.image_size = size;
+    }
+
+    //Shows the image that was rendered with the `image` view where it would be with `view`,
+    //stretched over the whole screen. While things are moving that's something to look at
+    //before the new image is done
+    pub fn show(&self, queue: &wgpu::Queue, image: &ShaderDataUniforms, view: &ShaderDataUniforms) {
+        //A pixel s away from the middle of the screen was at
+        //zoom'/zoom * rotate(s, rotation - rotation') + zoom' * rotate(position' - position, -rotation')
+        //on the image, done in fractions of the screen so it works on the texture coordinates
+        let [width, height] = view.resolution.map(|x| x.max(1) as f32);
+        let stretch = image.zoom / view.zoom;
+        let angle = view.rotation - image.rotation;
+        let x = rotate([stretch, 0.0], angle);
+        let y = rotate([0.0, stretch], angle);
+        let transform = [x[0], x[1] * width / height, y[0] * height / width, y[1]];
+        let moved = rotate(
+            [
+                (image.position[0] - view.position[0]) * image.zoom,
+                (image.position[1] - view.position[1]) * image.zoom,
+            ],
+            -image.rotation,
+        );
+        self.write_uniforms(queue, [moved[0] / width, moved[1] / height], transform);
+    }
+
+    fn write_uniforms(&self, queue: &wgpu::Queue, offset: [f32; 2], transform: [f32; 4]) {
+        let uniforms = [
+            self.image_size[0] as f32 / self.size[0] as f32,
+            self.image_size[1] as f32 / self.size[1] as f32,
+            offset[0],
+            offset[1],
+            transform[0],
+            transform[1],
+            transform[2],
+            transform[3],
+        ];
+        queue.write_buffer(&self.scale_buffer, 0, bytemuck::cast_slice(&uniforms));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_texture(device: &wgpu::Device, size: [u32; 2]) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Accumulation"),
+            size: wgpu::Extent3d {
+                width: size[0].max(1),
+                height: size[1].max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &BindGroupLayout,
+    scale_buffer: &Buffer,
+    texture_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: scale_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+//What gets added to the accumulation texture next
+#[derive(Clone, Copy)]
+pub enum Pass {
+    //Quick low resolution look while things are changing
+    Preview,
+    //Panning moves the iteration cache over by whole pixels and only iterates the ones that
+    //came into view, the picture is at `position` which is within a pixel of the real one
+    Shifted { shift: [i32; 2], position: [f32; 2] },
+    //The first sample at full resolution goes through the iteration cache, it only has to be
+    //iterated again if the orbits changed
+    Cached { iterate: bool },
+    //The rest of the samples get iterated and colored in one go, `count` of them starting at
+    //`first`. Progressive rendering does them one at a time, otherwise they all come at once
+    Sample { first: u32, count: u32 },
+}
+
+impl Pass {
+    //How much of the average in the accumulation texture the pass makes up once it's done
+    pub fn weight(self) -> f64 {
+        match self {
+            Self::Sample { first, count } => count as f64 / (first + count) as f64,
+            _ => 1.0,
+        }
+    }
+
+    //Fills the iteration cache with the compute shader before coloring it
+    pub fn iterates(self) -> bool {
+        matches!(self, Self::Shifted { .. } | Self::Cached { iterate: true })
+    }
+
+    //Colors what's in the iteration cache
+    pub fn is_cached(self) -> bool {
+        matches!(self, Self::Shifted { .. } | Self::Cached { .. })
+    }
+
+    //Slow enough to get split into tiles, which might take a few frames
+    pub fn is_tiled(self) -> bool {
+        self.iterates() || matches!(self, Self::Sample { .. })
+    }
+}
+
+//A pass that's partway done
+struct Current {
+    pass: Pass,
+    tiles: u32,
+    //What's in the iteration cache and the accumulation texture once it's done
+    cache: Option<(Vec<u32>, [f32; 2])>,
+    image: (Vec<u32>, ShaderDataUniforms),
+}
+
+//How far along the accumulation is
+#[derive(Default)]
+pub struct Progress {
+    samples: u32,
+    //The orbits in the iteration cache and the position it has them for, which is a bit off
+    //the real one after panning
+    cache: Option<(Vec<u32>, [f32; 2])>,
+    //How the accumulation texture looks and the view it has, for showing it moved
+    image: Option<(Vec<u32>, ShaderDataUniforms)>,
+    current: Option<Current>,
+    last_frame: Vec<u32>,
+}
+
+impl Progress {
+    pub fn is_done(&self, msaa: u32) -> bool {
+        self.samples >= msaa && self.current.is_none()
+    }
+
+    //View the accumulation texture was rendered with
+    pub fn image(&self) -> Option<ShaderDataUniforms> {
+        self.image.as_ref().map(|(_, image)| *image)
+    }
+
+    //How many tiles of the pass that's being worked on are done
+    pub fn tiles(&self) -> u32 {
+        self.current.as_ref().map_or(0, |current| current.tiles)
+    }
+
+    pub fn add_tiles(&mut self, tiles: u32) {
+        if let Some(current) = &mut self.current {
+            current.tiles += tiles;
+        }
+    }
+
+    //The current pass is all in the accumulation texture
+    pub fn finish(&mut self) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        if current.cache.is_some() {
+            self.cache = current.cache;
+        }
+        self.image = Some(current.image);
+        match current.pass {
+            Pass::Cached { .. } => self.samples += 1,
+            Pass::Sample { count, .. } => self.samples += count,
+            _ => {}
+        }
+    }
+
+    //Anything changing starts the samples over. While things are changing, panning shifts the
+    //iteration cache, zooming and turning just show the old image moved, and anything else
+    //gets a preview. Once they stop the first sample comes from the iteration cache, which only
+    //gets iterated again if the orbits changed. A pass that isn't done carries on. Without
+    //progressive rendering there's no previews, every change goes straight to the full picture
+    pub fn next_pass(
+        &mut self,
+        uniforms: ShaderDataUniforms,
+        orbit_params: &[u32],
+        layers: &[u32],
+        colors: &[f32],
+        progressive: bool,
+    ) -> Option<Pass> {
+        let look: Vec<u32> = ShaderDataUniforms {
+            position: [0.0, 0.0],
+            zoom: 0.0,
+            rotation: 0.0,
+            ..uniforms
+        }
+        .to_uniform_data()
+        .into_iter()
+        .chain(layers.iter().copied())
+        .chain(colors.iter().map(|x| x.to_bits()))
+        .collect();
+        let frame: Vec<u32> = uniforms
+            .to_uniform_data()
+            .into_iter()
+            .chain(look.iter().copied())
+            .collect();
+        let orbits: Vec<u32> = ShaderDataUniforms {
+            position: [0.0, 0.0],
+            msaa: 0,
+            num_layers: 0,
+            adaptive_threshold: 0.0,
+            ..uniforms
+        }
+        .to_uniform_data()
+        .into_iter()
+        .chain(orbit_params.iter().copied())
+        .collect();
+
+        let changed = frame != self.last_frame;
+        if changed {
+            self.last_frame = frame;
+            self.samples = 0;
+            self.current = None;
+        }
+        if let Some(current) = &self.current {
+            return Some(current.pass);
+        }
+        if self.is_done(uniforms.msaa) {
+            return None;
+        }
+        let (pass, cache, image) = if self.samples > 0 {
+            let count = if progressive {
+                1
+            } else {
+                uniforms.msaa - self.samples
+            };
+            let pass = Pass::Sample {
+                first: self.samples,
+                count,
+            };
+            (pass, None, uniforms)
+        } else {
+            let cached = self
+                .cache
+                .as_ref()
+                .filter(|(cached, _)| *cached == orbits)
+                .map(|(_, position)| *position);
+            if cached == Some(uniforms.position) {
+                (Pass::Cached { iterate: false }, None, uniforms)
+            } else if !changed {
+                let cache = Some((orbits, uniforms.position));
+                (Pass::Cached { iterate: true }, cache, uniforms)
+            } else if let Some(cached) = cached {
+                //Same orbits somewhere else is a pan
+                let moved = rotate(
+                    [
+                        (cached[0] - uniforms.position[0]) * uniforms.zoom,
+                        (cached[1] - uniforms.position[1]) * uniforms.zoom,
+                    ],
+                    -uniforms.rotation,
+                );
+                let shift = moved.map(|x| x.round() as i32);
+                let snapped = rotate(shift.map(|x| x as f32), uniforms.rotation);
+                let position = [
+                    cached[0] - snapped[0] / uniforms.zoom,
+                    cached[1] - snapped[1] / uniforms.zoom,
+                ];
+                let image = ShaderDataUniforms {
+                    position,
+                    ..uniforms
+                };
+                (
+                    Pass::Shifted { shift, position },
+                    Some((orbits, position)),
+                    image,
+                )
+            } else if !progressive {
+                //Anything but a pan gets the cache iterated again
+                let cache = Some((orbits, uniforms.position));
+                (Pass::Cached { iterate: true }, cache, uniforms)
+            } else {
+                //The old image moved is good enough until things stop changing
+                let stretch = self.image.as_ref().map(|(image_look, image)| {
+                    (*image_look == look).then_some(uniforms.zoom / image.zoom)
+                });
+                if let Some(Some(stretch)) = stretch {
+                    if (1.0 / MAX_STRETCH..=MAX_STRETCH).contains(&stretch) {
+                        return None;
+                    }
+                }
+                (Pass::Preview, None, uniforms)
+            }
+        };
+        if pass.iterates() {
+            //It's going to be half overwritten until the pass is done
+            self.cache = None;
+        }
+        self.current = Some(Current {
+            pass,
+            tiles: 0,
+            cache,
+            image: (look, image),
+        });
+        Some(pass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniforms() -> ShaderDataUniforms {
+        ShaderDataUniforms {
+            resolution: [200, 100],
+            zoom: 100.0,
+            max_iter: 100,
+            msaa: 2,
+            num_layers: 1,
+            ..Default::default()
+        }
+    }
+
+    fn next(progress: &mut Progress, uniforms: ShaderDataUniforms) -> Option<Pass> {
+        progress.next_pass(uniforms, &[], &[1], &[0.5], true)
+    }
+
+    //Gets a finished first sample into the iteration cache
+    fn settled() -> Progress {
+        let mut progress = Progress::default();
+        next(&mut progress, uniforms());
+        progress.finish();
+        next(&mut progress, uniforms());
+        progress.finish();
+        progress
+    }
+
+    #[test]
+    fn settles_into_samples() {
+        let mut progress = Progress::default();
+        assert!(matches!(
+            next(&mut progress, uniforms()),
+            Some(Pass::Preview)
+        ));
+        progress.finish();
+        assert!(matches!(
+            next(&mut progress, uniforms()),
+            Some(Pass::Cached { iterate: true })
+        ));
+        //Unfinished passes carry on
+        assert!(matches!(
+            next(&mut progress, uniforms()),
+            Some(Pass::Cached { iterate: true })
+        ));
+        progress.finish();
+        assert!(matches!(
+            next(&mut progress, uniforms()),
+            Some(Pass::Sample { first: 1, count: 1 })
+        ));
+        progress.finish();
+        assert!(next(&mut progress, uniforms()).is_none());
+        assert!(progress.is_done(2));
+    }
+
+    #[test]
+    fn recoloring_reuses_the_cache() {
+        let mut progress = settled();
+        assert!(matches!(
+            progress.next_pass(uniforms(), &[], &[2], &[0.5], true),
+            Some(Pass::Cached { iterate: false })
+        ));
+    }
+
+    #[test]
+    fn panning_shifts_by_whole_pixels() {
+        let mut progress = settled();
+        let panned = ShaderDataUniforms {
+            position: [-0.0105, 0.0],
+            ..uniforms()
+        };
+        let Some(Pass::Shifted { shift, position }) = next(&mut progress, panned) else {
+            panic!("expected a shift");
+        };
+        assert_eq!(shift, [1, 0]);
+        assert!((position[0] + 0.01).abs() < 1e-6 && position[1] == 0.0);
+        //Once it's done the cache is at the snapped position, stopping there reuses it
+        progress.finish();
+        let stopped = ShaderDataUniforms {
+            position,
+            ..uniforms()
+        };
+        next(&mut progress, stopped);
+        assert!(matches!(
+            next(&mut progress, stopped),
+            Some(Pass::Cached { iterate: false })
+        ));
+    }
+
+    #[test]
+    fn zooming_shows_the_old_image() {
+        let mut progress = settled();
+        let zoomed = |zoom| ShaderDataUniforms { zoom, ..uniforms() };
+        assert!(next(&mut progress, zoomed(150.0)).is_none());
+        assert!(matches!(
+            next(&mut progress, zoomed(1000.0)),
+            Some(Pass::Preview)
+        ));
+    }
+
+    #[test]
+    fn all_at_once_without_progressive() {
+        let mut progress = Progress::default();
+        let uniforms = ShaderDataUniforms {
+            msaa: 4,
+            ..uniforms()
+        };
+        let next = |progress: &mut Progress, uniforms| {
+            progress.next_pass(uniforms, &[], &[1], &[0.5], false)
+        };
+        assert!(matches!(
+            next(&mut progress, uniforms),
+            Some(Pass::Cached { iterate: true })
+        ));
+        progress.finish();
+        let pass = next(&mut progress, uniforms).unwrap();
+        assert!(matches!(pass, Pass::Sample { first: 1, count: 3 }));
+        assert_eq!(pass.weight(), 0.75);
+        progress.finish();
+        assert!(progress.is_done(4));
+        //Zooming doesn't show the old image, it gets the whole picture again
+        let zoomed = ShaderDataUniforms {
+            zoom: 150.0,
+            ..uniforms
+        };
+        assert!(matches!(
+            next(&mut progress, zoomed),
+            Some(Pass::Cached { iterate: true })
+        ));
+    }
+}
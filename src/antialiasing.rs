@@ -0,0 +1,67 @@
+//Where the anti aliasing samples go inside a pixel
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum SamplePattern {
+    //Same random offsets for every sample count, what it always used to do
+    Random = 0,
+    //One jittered sample in each cell of a grid, best with a square number of samples
+    Stratified = 1,
+    RotatedGrid = 2,
+    Halton = 3,
+    //Low discrepancy too, but spreads out evenly for any number of samples
+    #[default]
+    R2 = 4,
+}
+
+impl std::fmt::Display for SamplePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Random => write!(f, "Random"),
+            Self::Stratified => write!(f, "Stratified grid"),
+            Self::RotatedGrid => write!(f, "Rotated grid"),
+            Self::Halton => write!(f, "Halton"),
+            Self::R2 => write!(f, "R2"),
+        }
+    }
+}
+
+impl SamplePattern {
+    pub const ALL: [Self; 5] = [
+        Self::Random,
+        Self::Stratified,
+        Self::RotatedGrid,
+        Self::Halton,
+        Self::R2,
+    ];
+}
+
+//How the samples get weighted when they're put together into a pixel
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Filter {
+    Box = 0,
+    #[default]
+    Tent = 1,
+    Gaussian = 2,
+    //Sharpest, but can ring around hard edges
+    Lanczos = 3,
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Box => write!(f, "Box"),
+            Self::Tent => write!(f, "Tent"),
+            Self::Gaussian => write!(f, "Gaussian"),
+            Self::Lanczos => write!(f, "Lanczos"),
+        }
+    }
+}
+
+impl Filter {
+    pub const ALL: [Self; 4] = [Self::Box, Self::Tent, Self::Gaussian, Self::Lanczos];
+}
+
+//The top bit of the pattern uniform shifts the pattern by a random amount for each pixel, so
+//neighbouring pixels don't make the same mistakes
+pub fn pattern_flags(pattern: SamplePattern, per_pixel: bool) -> u32 {
+    pattern as u32 | if per_pixel { 2147483648 } else { 0 }
+}
@@ -0,0 +1,1065 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use iced_wgpu::{wgpu, Viewport};
+use iced_winit::{
+    clipboard, command, conversion, futures, image, keyboard, mouse, program, renderer, touch,
+    winit::{
+        dpi::{PhysicalPosition, PhysicalSize},
+        event::{ElementState, ModifiersState, MouseButton, TouchPhase, WindowEvent},
+        event_loop::ControlFlow,
+        window::{Fullscreen, Window},
+    },
+    Clipboard, Color, Debug, Size,
+};
+
+use crate::{
+    accumulation::{Accumulation, Pass, Progress},
+    antialiasing,
+    camera::{rotate, Camera},
+    coloring,
+    controls::{self, Controls, Message},
+    history::{self, HistoryEntry, THUMBNAIL_WIDTH},
+    iterations::{AutoIterations, MAX_ITERATIONS},
+    keybindings::Action,
+    minimap::{self, Minimap},
+    orbit::Orbit,
+    overlay::Overlay,
+    scene::{self, Scene},
+    screenshot, tiles,
+};
+
+//Touchpads scroll in pixels, this is roughly how many make up one notch of a mouse wheel
+const PIXELS_PER_LINE: f32 = 50.0;
+
+//How far one press of a pan key moves, as a fraction of the smaller side of the window
+const PAN_STEP: f32 = 0.1;
+
+//How far one press of a rotate key turns the view, in degrees
+const ROTATION_STEP: f32 = 15.0;
+
+const ORBIT_COLOR: Color = Color::from_rgb(1.0, 0.8, 0.0);
+
+//How much smaller the picture gets while it's changing, when rendering progressively
+const PREVIEW_SCALE: u32 = 2;
+
+//How often to redraw while the camera is moving
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+
+//Offset from the middle of the window in pixels, which is what the shader works with
+fn screen_offset(position: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> [f32; 2] {
+    [
+        position.x as f32 - size.width as f32 * 0.5,
+        position.y as f32 - size.height as f32 * 0.5,
+    ]
+}
+
+//Angle of a point around the middle of the window in degrees
+fn screen_angle(position: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> f32 {
+    let offset = screen_offset(position, size);
+    offset[1].atan2(offset[0]).to_degrees()
+}
+
+//Same shape as the window
+fn thumbnail_size(size: PhysicalSize<u32>) -> [u32; 2] {
+    let height = THUMBNAIL_WIDTH * size.height / size.width.max(1);
+    [THUMBNAIL_WIDTH, height.max(1)]
+}
+
+//Center of all the fingers and their average distance from it
+fn touch_center(touches: &HashMap<u64, PhysicalPosition<f64>>) -> (PhysicalPosition<f64>, f64) {
+    let count = touches.len().max(1) as f64;
+    let center = touches
+        .values()
+        .fold(PhysicalPosition::new(0.0, 0.0), |acc, p| {
+            PhysicalPosition::new(acc.x + p.x / count, acc.y + p.y / count)
+        });
+    let distance = touches
+        .values()
+        .map(|p| ((p.x - center.x).powi(2) + (p.y - center.y).powi(2)).sqrt())
+        .sum::<f64>()
+        / count;
+    (center, distance)
+}
+
+//The uniforms a pass gets rendered with. With progressive rendering a change gets one quick pass
+//at a lower resolution, after that the samples get added one per frame at full resolution
+fn pass_uniforms(pass: Pass, uniforms: scene::ShaderDataUniforms) -> scene::ShaderDataUniforms {
+    match pass {
+        Pass::Preview => {
+            let [width, height] = uniforms.resolution;
+            let resolution = [
+                width.div_ceil(PREVIEW_SCALE),
+                height.div_ceil(PREVIEW_SCALE),
+            ];
+            scene::ShaderDataUniforms {
+                resolution,
+                zoom: uniforms.zoom * resolution[0] as f32 / width as f32,
+                msaa: 1,
+                ..uniforms
+            }
+        }
+        Pass::Shifted { position, .. } => scene::ShaderDataUniforms {
+            position,
+            msaa: 1,
+            sample: 0,
+            ..uniforms
+        },
+        Pass::Cached { .. } => scene::ShaderDataUniforms {
+            msaa: 1,
+            sample: 0,
+            ..uniforms
+        },
+        Pass::Sample { first, count } => scene::ShaderDataUniforms {
+            msaa: count,
+            sample: first,
+            ..uniforms
+        },
+    }
+}
+
+//Everything the event loop needs, so none of it has to live in globals
+pub struct App {
+    window: Window,
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
+    size: PhysicalSize<u32>,
+    resized: bool,
+    staging_belt: wgpu::util::StagingBelt,
+    scene: Scene,
+    accumulation: Accumulation,
+    progress: Progress,
+    tiles: tiles::Scheduler,
+    auto_iters: AutoIterations,
+    overlay: Overlay,
+    renderer: iced_wgpu::Renderer,
+    debug: Debug,
+    state: program::State<Controls>,
+    clipboard: Clipboard,
+    cursor_position: PhysicalPosition<f64>,
+    modifiers: ModifiersState,
+    closing: bool,
+    camera: Camera,
+    //When to draw the next frame of the camera animation, None when it's standing still
+    next_frame: Option<Instant>,
+    //When the camera last moved, none while it stands still
+    camera_clock: Option<Instant>,
+    dragging: bool,
+    //Dragging the view around on the minimap
+    dragging_minimap: bool,
+    //Ctrl-dragging rotates the view around the middle of the window
+    rotating: bool,
+    //The last place that went into the navigation history
+    last_location: Option<history::Location>,
+    //Where a shift-drag rectangle selection started
+    selection: Option<PhysicalPosition<f64>>,
+    //Fingers that started on the fractal and not on the UI
+    touches: HashMap<u64, PhysicalPosition<f64>>,
+}
+
+impl App {
+    pub fn new(window: Window) -> Self {
+        let size = window.inner_size();
+        let viewport =
+            Viewport::with_physical_size(Size::new(size.width, size.height), window.scale_factor());
+        let clipboard = Clipboard::connect(&window);
+
+        let default_backend = wgpu::Backends::PRIMARY;
+
+        let backend = wgpu::util::backend_bits_from_env().unwrap_or(default_backend);
+        let instance = wgpu::Instance::new(backend);
+        //Mom come pick me up I'm scared
+        //It's not that bad tho, it's just that the wgpu API is unsafe and could access invalid memory
+        //well at least according to ChatGPT
+        let surface = unsafe { instance.create_surface(&window) };
+
+        let (format, (device, queue)) = futures::executor::block_on(async {
+            let adapter = wgpu::util::initialize_adapter_from_env_or_default(
+                &instance,
+                backend,
+                Some(&surface),
+            )
+            .await
+            .expect("No suitabple GPUs found");
+            let adapter_features = adapter.features();
+
+            let needed_limits = wgpu::Limits::default();
+
+            //Both the UI and the fractal work out colors in linear space and leave the encoding to
+            //an sRGB surface. The fractal shaders can do it themselves if there isn't one, iced
+            //can't so the UI comes out a bit dark then
+            let formats = surface.get_supported_formats(&adapter);
+            (
+                formats
+                    .iter()
+                    .find(|format| format.describe().srgb)
+                    .or_else(|| formats.first())
+                    .copied()
+                    .expect("Get preferred format"),
+                adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor {
+                            label: None,
+                            features: adapter_features & wgpu::Features::default(),
+                            limits: needed_limits,
+                        },
+                        None,
+                    )
+                    .await
+                    .expect("Request device"),
+            )
+        });
+
+        //A buffer for transferring things to and from the GPU memory
+        //Assuming 8 layers with 256 colors each the size needs to be
+        //48(uniforms) + 112 * 8(layers) + 32 * 256 * 8(colors) = 66480
+        //I'm going to go for 128k just in case and cause it's a nicer number
+        let staging_belt = wgpu::util::StagingBelt::new(131072);
+
+        println!("Format is {:#?}", format);
+        let scene = Scene::new(&device, format, [size.width, size.height]);
+        let overlay = Overlay::new(&device, format);
+        let accumulation = Accumulation::new(&device, format, [size.width, size.height]);
+
+        let mut debug = Debug::new();
+        let mut renderer = iced_wgpu::Renderer::new(iced_wgpu::Backend::new(
+            &device,
+            iced_wgpu::Settings::default(),
+            format,
+        ));
+
+        let state = program::State::new(
+            Controls::new(),
+            viewport.logical_size(),
+            &mut renderer,
+            &mut debug,
+        );
+
+        let app = Self {
+            window,
+            surface,
+            device,
+            queue,
+            format,
+            viewport,
+            size,
+            resized: false,
+            staging_belt,
+            scene,
+            accumulation,
+            progress: Progress::default(),
+            tiles: tiles::Scheduler::default(),
+            auto_iters: AutoIterations::default(),
+            overlay,
+            renderer,
+            debug,
+            state,
+            clipboard,
+            cursor_position: PhysicalPosition::new(-1.0, -1.0),
+            modifiers: ModifiersState::default(),
+            closing: false,
+            camera: Camera::new(controls::View {
+                position: [0.0, 0.0],
+                zoom: controls::BASE_ZOOM,
+            }),
+            next_frame: None,
+            camera_clock: None,
+            dragging: false,
+            dragging_minimap: false,
+            rotating: false,
+            last_location: None,
+            selection: None,
+            touches: HashMap::new(),
+        };
+        app.configure_surface();
+        app
+    }
+
+    fn configure_surface(&self) {
+        self.surface.configure(
+            &self.device,
+            &wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: self.format,
+                width: self.size.width,
+                height: self.size.height,
+                present_mode: wgpu::PresentMode::AutoVsync,
+                alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            },
+        );
+    }
+
+    //Waits for input unless the camera is still moving
+    pub fn control_flow(&self) -> ControlFlow {
+        match self.next_frame {
+            _ if self.closing => ControlFlow::Exit,
+            Some(time) => ControlFlow::WaitUntil(time),
+            None => ControlFlow::Wait,
+        }
+    }
+
+    //Called when the time from control_flow is up
+    pub fn next_frame(&mut self) {
+        if self.next_frame.is_some() {
+            self.window.request_redraw();
+        }
+    }
+
+    pub fn window_event(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let rotation = self.state.program().rotation.to_radians();
+                if self.dragging {
+                    let delta = rotate(
+                        [
+                            ((position.x - self.cursor_position.x) / self.camera.zoom) as f32,
+                            ((position.y - self.cursor_position.y) / self.camera.zoom) as f32,
+                        ],
+                        rotation,
+                    );
+                    self.camera.pan(delta);
+                }
+                //The minimap moves the other way, and faster since it's zoomed out
+                if self.dragging_minimap {
+                    let zoom = self.minimap().view().zoom;
+                    self.camera.pan([
+                        ((self.cursor_position.x - position.x) / zoom) as f32,
+                        ((self.cursor_position.y - position.y) / zoom) as f32,
+                    ]);
+                }
+                //Where the cursor is on the plane, for the coordinate readout
+                let offset = rotate(screen_offset(position, self.size), rotation);
+                *self.state.program().cursor_coordinate.lock().unwrap() =
+                    Some(self.camera.to_plane(offset));
+                if self.rotating {
+                    //The fractal follows the cursor around
+                    let delta = screen_angle(position, self.size)
+                        - screen_angle(self.cursor_position, self.size);
+                    self.state.queue_message(Message::RotateBy(-delta));
+                }
+                self.cursor_position = position;
+                //So the tooltip is already up to date when the UI gets this event
+                *self.state.program().orbit_summary.lock().unwrap() =
+                    self.orbit().map(|orbit| orbit.to_string());
+            }
+            WindowEvent::CursorLeft { .. } => {
+                *self.state.program().cursor_coordinate.lock().unwrap() = None;
+                *self.state.program().orbit_summary.lock().unwrap() = None;
+            }
+            WindowEvent::ModifiersChanged(new_modifiers) => {
+                self.modifiers = new_modifiers;
+            }
+            WindowEvent::Resized(_) => {
+                self.resized = true;
+                self.size = self.window.inner_size();
+            }
+            WindowEvent::CloseRequested => self.closing = true,
+            //Presses and scrolling only count if the UI didn't capture them, so they're
+            //handled after the UI update, but releasing always stops dragging
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state: ElementState::Released,
+                ..
+            } => {
+                self.dragging = false;
+                self.dragging_minimap = false;
+                self.rotating = false;
+                if let Some(start) = self.selection.take() {
+                    self.zoom_to_selection(start);
+                    self.window.request_redraw();
+                }
+            }
+            WindowEvent::Touch(touch) if self.touches.contains_key(&touch.id) => {
+                match touch.phase {
+                    TouchPhase::Moved => {
+                        let (old_center, old_distance) = touch_center(&self.touches);
+                        self.touches.insert(touch.id, touch.location);
+                        let (center, distance) = touch_center(&self.touches);
+                        let rotation = self.state.program().rotation.to_radians();
+                        let delta = rotate(
+                            [
+                                ((center.x - old_center.x) / self.camera.zoom) as f32,
+                                ((center.y - old_center.y) / self.camera.zoom) as f32,
+                            ],
+                            rotation,
+                        );
+                        self.camera.pan(delta);
+                        //Pinching
+                        if self.touches.len() > 1 && old_distance > 0.0 {
+                            self.camera.zoom_at(
+                                (distance / old_distance) as f32,
+                                rotate(screen_offset(center, self.size), rotation),
+                            );
+                        }
+                    }
+                    TouchPhase::Ended | TouchPhase::Cancelled => {
+                        self.touches.remove(&touch.id);
+                    }
+                    TouchPhase::Started => {}
+                }
+            }
+            _ => {}
+        }
+        if let Some(event) =
+            conversion::window_event(&event, self.window.scale_factor(), self.modifiers)
+        {
+            self.state.queue_event(event);
+        }
+    }
+
+    //Zoom so the selected rectangle fills the window
+    fn zoom_to_selection(&mut self, start: PhysicalPosition<f64>) {
+        let end = self.cursor_position;
+        let width = (end.x - start.x).abs() as f32;
+        let height = (end.y - start.y).abs() as f32;
+        //Too small to be anything but a misclick
+        if width <= 4.0 || height <= 4.0 {
+            return;
+        }
+        let center = rotate(
+            screen_offset(
+                PhysicalPosition::new((start.x + end.x) * 0.5, (start.y + end.y) * 0.5),
+                self.size,
+            ),
+            self.state.program().rotation.to_radians(),
+        );
+        self.camera.fly_to(controls::View {
+            position: self.camera.to_plane(center).map(|x| -x),
+            zoom: self.camera.zoom
+                * f64::from((self.size.width as f32 / width).min(self.size.height as f32 / height)),
+        });
+    }
+
+    fn minimap(&self) -> Minimap {
+        Minimap::new(self.size, self.window.scale_factor())
+    }
+
+    //Corners of the window on the plane, going around clockwise from the top left
+    fn view_corners(&self) -> [[f32; 2]; 4] {
+        let [x, y] = [self.size.width as f32 * 0.5, self.size.height as f32 * 0.5];
+        let rotation = self.state.program().rotation.to_radians();
+        [[-x, -y], [x, -y], [x, y], [-x, y]].map(|corner| {
+            self.camera
+                .to_plane(rotate(corner, rotation))
+                .map(|x| x as f32)
+        })
+    }
+
+    //Pixel of the window showing a point on the plane
+    fn plane_to_screen(&self, point: [f32; 2]) -> [f32; 2] {
+        let offset = rotate(
+            self.camera.to_offset(point.map(f64::from)),
+            -self.state.program().rotation.to_radians(),
+        );
+        [
+            offset[0] + self.size.width as f32 * 0.5,
+            offset[1] + self.size.height as f32 * 0.5,
+        ]
+    }
+
+    //Orbit of the point under the cursor, if it's turned on and the cursor is in the window
+    fn orbit(&self) -> Option<Orbit> {
+        let program = self.state.program();
+        if !program.show_orbit || program.cursor_coordinate.lock().unwrap().is_none() {
+            return None;
+        }
+        let offset = rotate(
+            screen_offset(self.cursor_position, self.size),
+            program.rotation.to_radians(),
+        );
+        let c = self.camera.to_plane(offset).map(|x| x as f32);
+        Some(Orbit::new(program.current_fractal, c, program.num_iters))
+    }
+
+    //Clicking outside the view rectangle jumps there, either way it can be dragged after
+    fn press_minimap(&mut self, minimap: &Minimap, cursor: [f32; 2]) {
+        let point = minimap.to_plane(cursor);
+        let offset = rotate(
+            self.camera.to_offset(point.map(f64::from)),
+            -self.state.program().rotation.to_radians(),
+        );
+        let inside = offset[0].abs() <= self.size.width as f32 * 0.5
+            && offset[1].abs() <= self.size.height as f32 * 0.5;
+        if !inside {
+            self.camera.fly_to(controls::View {
+                position: point.map(|x| -f64::from(x)),
+                zoom: self.camera.zoom,
+            });
+        }
+        self.dragging_minimap = true;
+    }
+
+    pub fn update_ui(&mut self) {
+        //Keyboard shortcuts queue messages for the UI, so keep going until those are handled too
+        while !self.state.is_queue_empty() {
+            let (uncaptured, command) = self.state.update(
+                self.viewport.logical_size(),
+                conversion::cursor_position(self.cursor_position, self.viewport.scale_factor()),
+                &mut self.renderer,
+                &iced_wgpu::Theme::Light,
+                &renderer::Style {
+                    text_color: Color::WHITE,
+                },
+                &mut self.clipboard,
+                &mut self.debug,
+            );
+            //The clipboard is the only thing the UI asks for
+            for action in command.map(|c| c.actions()).unwrap_or_default() {
+                if let command::Action::Clipboard(clipboard::Action::Write(contents)) = action {
+                    self.clipboard.write(contents);
+                }
+            }
+            for event in uncaptured {
+                self.uncaptured_event(event);
+            }
+            self.window.request_redraw();
+        }
+    }
+
+    fn uncaptured_event(&mut self, event: iced_winit::Event) {
+        let rotation = self.state.program().rotation.to_radians();
+        let zoom_factor = self.state.program().zoom_factor;
+        match event {
+            iced_winit::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let minimap = self.minimap();
+                let cursor = [self.cursor_position.x as f32, self.cursor_position.y as f32];
+                if self.state.program().show_minimap && minimap.contains(cursor) {
+                    self.press_minimap(&minimap, cursor);
+                } else if self.modifiers.shift() {
+                    self.selection = Some(self.cursor_position);
+                } else if self.modifiers.ctrl() {
+                    self.rotating = true;
+                } else {
+                    self.dragging = true;
+                }
+            }
+            //Right click zooms out around the cursor
+            iced_winit::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                self.camera.zoom_at(
+                    1.0 / zoom_factor,
+                    rotate(screen_offset(self.cursor_position, self.size), rotation),
+                );
+            }
+            iced_winit::Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => y,
+                    mouse::ScrollDelta::Pixels { y, .. } => y / PIXELS_PER_LINE,
+                };
+                self.camera.zoom_at(
+                    zoom_factor.powf(lines),
+                    rotate(screen_offset(self.cursor_position, self.size), rotation),
+                );
+            }
+            iced_winit::Event::Touch(touch::Event::FingerPressed { id, position }) => {
+                let scale_factor = self.viewport.scale_factor();
+                self.touches.insert(
+                    id.0,
+                    PhysicalPosition::new(
+                        position.x as f64 * scale_factor,
+                        position.y as f64 * scale_factor,
+                    ),
+                );
+            }
+            iced_winit::Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code,
+                modifiers,
+            }) => {
+                let action = self.state.program().keybindings.action(key_code, modifiers);
+                if let Some(action) = action {
+                    self.action(action);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn action(&mut self, action: Action) {
+        let program = self.state.program();
+        let (rotation, zoom_factor) = (program.rotation.to_radians(), program.zoom_factor);
+        let step = (f64::from(PAN_STEP * self.size.width.min(self.size.height) as f32)
+            / self.camera.zoom) as f32;
+        match action {
+            Action::PanLeft => self.camera.pan(rotate([step, 0.0], rotation)),
+            Action::PanRight => self.camera.pan(rotate([-step, 0.0], rotation)),
+            Action::PanUp => self.camera.pan(rotate([0.0, step], rotation)),
+            Action::PanDown => self.camera.pan(rotate([0.0, -step], rotation)),
+            Action::RotateLeft => self.state.queue_message(Message::RotateBy(ROTATION_STEP)),
+            Action::RotateRight => self.state.queue_message(Message::RotateBy(-ROTATION_STEP)),
+            //Zoom around the middle of the window
+            Action::ZoomIn => self.camera.zoom_at(zoom_factor, [0.0, 0.0]),
+            Action::ZoomOut => self.camera.zoom_at(1.0 / zoom_factor, [0.0, 0.0]),
+            Action::ResetView => {
+                self.camera.fly_to(controls::View {
+                    position: [0.0, 0.0],
+                    zoom: controls::BASE_ZOOM,
+                });
+                self.state.queue_message(Message::RotationChanged(0.0));
+            }
+            Action::Back => self.state.queue_message(Message::HistoryBack),
+            Action::Forward => self.state.queue_message(Message::HistoryForward),
+            Action::ToggleUi => self.state.queue_message(Message::ToggleUi),
+            Action::ToggleHelp => self.state.queue_message(Message::ToggleHelp),
+            Action::CycleFractal => {
+                let next = program.current_fractal.next();
+                self.state.queue_message(Message::ChangeFractal(next));
+            }
+            Action::MoreIters | Action::FewerIters => {
+                let iters = program.num_iters;
+                let iters = if action == Action::MoreIters {
+                    (iters + iters / 4).max(iters + 1).min(MAX_ITERATIONS)
+                } else {
+                    (iters - iters / 5).max(1)
+                };
+                self.state.queue_message(Message::NumItersChanged(iters));
+            }
+            Action::ToggleOrbit => {
+                let show = !program.show_orbit;
+                self.state.queue_message(Message::ToggleOrbit(show));
+            }
+            Action::Screenshot => self.state.queue_message(Message::ScreenshotClick),
+            Action::Fullscreen => {
+                self.window.set_fullscreen(match self.window.fullscreen() {
+                    Some(_) => None,
+                    None => Some(Fullscreen::Borderless(None)),
+                });
+            }
+        }
+    }
+
+    pub fn redraw(&mut self) {
+        if self.resized {
+            self.viewport = Viewport::with_physical_size(
+                Size::new(self.size.width, self.size.height),
+                self.window.scale_factor(),
+            );
+            self.configure_surface();
+            self.accumulation
+                .resize(&self.device, [self.size.width, self.size.height]);
+            //The resolution is part of the orbits, so the empty cache gets filled again
+            self.scene
+                .resize(&self.device, [self.size.width, self.size.height]);
+            self.resized = false;
+        }
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(_) => todo!(),
+        };
+
+        let program = self.state.program();
+        //Loading a scene jumps straight to its location
+        if let Some(view) = program.pending_view.lock().unwrap().take() {
+            self.camera.jump_to(view);
+        }
+        //Bookmarks fly there instead
+        if let Some(view) = program.pending_flight.lock().unwrap().take() {
+            self.camera.fly_to(view);
+        }
+        self.camera.smoothing = if program.smooth_navigation {
+            program.smoothing
+        } else {
+            0.0
+        };
+        //Keep the frames coming while the camera moves. The clock only runs while it's moving so
+        //the first step isn't as long as the time spent standing still
+        let now = Instant::now();
+        let dt = self
+            .camera_clock
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.camera_clock = self.camera.update(dt).then_some(now);
+        self.next_frame = self.camera_clock.map(|now| now + FRAME_TIME);
+        *program.view.lock().unwrap() = self.camera.view();
+
+        self.overlay.clear();
+        let minimap = self.minimap();
+        let corners = self.view_corners();
+        let minimap_uniforms = if program.show_minimap {
+            let deep = minimap.draw(
+                &mut self.overlay,
+                self.camera.position.map(|x| -x as f32),
+                corners,
+            );
+            *program.minimap_depth.lock().unwrap() = deep.then_some(self.camera.zoom);
+            let view = minimap.view();
+            Some(scene::ShaderDataUniforms {
+                position: view.position.map(|x| x as f32),
+                resolution: [minimap.size[0] as u32, minimap.size[1] as u32],
+                aspect: minimap.size[0] / minimap.size[1],
+                zoom: view.zoom as f32,
+                max_iter: program.num_iters.min(minimap::MAX_ITERS),
+                fractal: scene::fractal_flags(program.current_fractal, program.smooth_enabled),
+                msaa: 1,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+        let orbit = self.orbit();
+        if let Some(orbit) = &orbit {
+            let points: Vec<_> = orbit
+                .points
+                .iter()
+                .map(|p| self.plane_to_screen(*p))
+                .collect();
+            for pair in points.windows(2) {
+                self.overlay.line(pair[0], pair[1], ORBIT_COLOR);
+            }
+        }
+        *program.orbit_summary.lock().unwrap() = orbit.map(|orbit| orbit.to_string());
+        if let Some(start) = self.selection {
+            self.overlay.rectangle(
+                [start.x as f32, start.y as f32],
+                [self.cursor_position.x as f32, self.cursor_position.y as f32],
+                Color::WHITE,
+            );
+        }
+        self.overlay
+            .upload(&self.queue, self.size.width, self.size.height);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        let view = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let (raw_layers, raw_colors) = coloring::layers_raw(&program.layers);
+        let uniforms = scene::ShaderDataUniforms {
+            aspect: self.size.width as f32 / self.size.height as f32,
+            rotation: program.rotation.to_radians(),
+            resolution: [self.size.width, self.size.height],
+            num_layers: (raw_layers.len() / coloring::LAYER_SIZE) as u32,
+            max_iter: program.num_iters,
+            fractal: scene::fractal_flags(program.current_fractal, program.smooth_enabled),
+            msaa: program.msaa,
+            zoom: self.camera.zoom as f32,
+            position: self.camera.position.map(|x| x as f32),
+            period_tolerance: if program.period_checking {
+                program.period_tolerance
+            } else {
+                0.0
+            },
+            aa_pattern: antialiasing::pattern_flags(program.aa_pattern, program.aa_per_pixel),
+            aa_filter: program.aa_filter as u32,
+            aa_samples: program.msaa,
+            adaptive_threshold: if program.adaptive_aa {
+                program.adaptive_threshold
+            } else {
+                0.0
+            },
+            ..Default::default()
+        };
+        let pass = self.progress.next_pass(
+            uniforms,
+            &coloring::orbit_params(&program.layers),
+            &raw_layers,
+            &raw_colors,
+            program.progressive,
+        );
+        let pass_uniforms = pass.map(|pass| pass_uniforms(pass, uniforms));
+        let raw_data = pass_uniforms.unwrap_or(uniforms).to_uniform_data();
+
+        self.staging_belt
+            .write_buffer(
+                &mut encoder,
+                &self.scene.buffer,
+                0,
+                wgpu::BufferSize::new((raw_data.len() * 4) as wgpu::BufferAddress).unwrap(),
+                &self.device,
+            )
+            .copy_from_slice(bytemuck::cast_slice(&raw_data));
+        self.staging_belt
+            .write_buffer(
+                &mut encoder,
+                &self.scene.storage_buffer,
+                0,
+                wgpu::BufferSize::new((raw_colors.len() * 4) as wgpu::BufferAddress).unwrap(),
+                &self.device,
+            )
+            .copy_from_slice(bytemuck::cast_slice(&raw_colors));
+        self.staging_belt
+            .write_buffer(
+                &mut encoder,
+                &self.scene.layer_buffer,
+                0,
+                wgpu::BufferSize::new((raw_layers.len() * 4) as wgpu::BufferAddress).unwrap(),
+                &self.device,
+            )
+            .copy_from_slice(bytemuck::cast_slice(&raw_layers));
+        if let Some(minimap_uniforms) = minimap_uniforms {
+            let raw_data = scene::ShaderDataUniforms {
+                num_layers: uniforms.num_layers,
+                ..minimap_uniforms
+            }
+            .to_uniform_data();
+            self.staging_belt
+                .write_buffer(
+                    &mut encoder,
+                    &self.scene.minimap_buffer,
+                    0,
+                    wgpu::BufferSize::new((raw_data.len() * 4) as wgpu::BufferAddress).unwrap(),
+                    &self.device,
+                )
+                .copy_from_slice(bytemuck::cast_slice(&raw_data));
+        }
+
+        //The tiles go in submissions of their own, which need the uniforms to be there already
+        self.staging_belt.finish();
+        self.queue.submit(Some(encoder.finish()));
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        //Iterating that isn't done yet gets shown over the old picture
+        let mut unfinished = None;
+        //How much of a whole new picture ran out of iterations, for auto iterations
+        let mut stats = None;
+        if let (Some(pass), Some(pass_uniforms)) = (pass, pass_uniforms) {
+            let resolution = pass_uniforms.resolution;
+            let count = tiles::count(resolution);
+            let done = if pass.is_tiled() {
+                let first = self.progress.tiles();
+                if pass.iterates() && first == 0 {
+                    let shift = match pass {
+                        Pass::Shifted { shift, .. } => Some(shift),
+                        _ => None,
+                    };
+                    self.scene.shift_cache(&self.queue, shift);
+                }
+                let scene = &self.scene;
+                let queue = &self.queue;
+                let target = self.accumulation.texture_view();
+                let done =
+                    self.tiles
+                        .run(&self.device, &self.queue, first..count, |encoder, tiles| {
+                            if pass.iterates() {
+                                scene.compute(queue, encoder, tiles);
+                            } else {
+                                scene.accumulate(
+                                    encoder,
+                                    target,
+                                    resolution,
+                                    pass.weight(),
+                                    false,
+                                    tiles,
+                                );
+                            }
+                        });
+                self.progress.add_tiles(done - first);
+                done
+            } else {
+                count
+            };
+            if done < count {
+                if pass.iterates() {
+                    unfinished = Some((resolution, 0..done));
+                }
+            } else {
+                //Samples are already in there, the rest get colored now
+                if !pass.is_tiled() || pass.iterates() {
+                    self.scene.accumulate(
+                        &mut encoder,
+                        self.accumulation.texture_view(),
+                        resolution,
+                        pass.weight(),
+                        pass.is_cached(),
+                        0..count,
+                    );
+                }
+                self.accumulation.set_image_size(resolution);
+                self.progress.finish();
+                //Without period checking every pixel inside the set looks like it ran out of
+                //iterations, so the count only follows the zoom
+                if program.auto_iters
+                    && program.period_checking
+                    && matches!(pass, Pass::Cached { iterate: true })
+                {
+                    stats = Some(self.scene.iteration_stats(&self.device, &self.queue));
+                }
+            }
+        }
+        let image = self.progress.image().unwrap_or(uniforms);
+        self.accumulation.show(&self.queue, &image, &uniforms);
+        if !self.progress.is_done(program.msaa) {
+            self.next_frame.get_or_insert_with(Instant::now);
+        }
+        {
+            let mut render_pass = self.scene.clear(&view, &mut encoder);
+            render_pass.set_bind_group(0, &self.scene.bind_group, &[]);
+            self.accumulation.draw(&mut render_pass);
+            if let Some((resolution, tiles)) = unfinished {
+                self.scene.draw_tiles(
+                    &mut render_pass,
+                    resolution,
+                    tiles,
+                    [self.size.width, self.size.height],
+                );
+            }
+            if minimap_uniforms.is_some() {
+                self.scene.draw_minimap(
+                    &mut render_pass,
+                    minimap.origin,
+                    minimap.size,
+                    [self.size.width, self.size.height],
+                );
+            }
+            self.overlay.draw(&mut render_pass);
+        }
+
+        self.renderer.with_primitives(|backend, primitive| {
+            backend.present(
+                &self.device,
+                &mut self.staging_belt,
+                &mut encoder,
+                &view,
+                primitive,
+                &self.viewport,
+                &self.debug.overlay(),
+            );
+        });
+        self.staging_belt.finish();
+        self.queue.submit(Some(encoder.finish()));
+
+        let mut pending = program.pending_screenshot.lock().unwrap();
+        if *pending {
+            *pending = false;
+            let (width, height) = (self.size.width, self.size.height);
+            let saved = if program.export_format.is_float() {
+                let pixels =
+                    self.scene
+                        .capture_float(&self.device, &self.queue, uniforms, &program.layers);
+                screenshot::save_float(pixels, width, height, program.export_format)
+            } else {
+                let pixels =
+                    self.scene
+                        .capture(&self.device, &self.queue, uniforms, &program.layers);
+                screenshot::save(pixels, width, height)
+            };
+            match saved {
+                Ok(path) => println!("Saved screenshot to {}", path.display()),
+                Err(e) => eprintln!("Couldn't save screenshot: {e}"),
+            }
+        }
+        drop(pending);
+
+        frame.present();
+        self.staging_belt.recall();
+
+        self.record_history(uniforms);
+        self.render_bookmark_thumbnail();
+        self.auto_iterations(stats);
+    }
+
+    //Panning, zooming, turning or selecting
+    fn is_moving(&self) -> bool {
+        self.camera.is_moving()
+            || self.dragging
+            || self.dragging_minimap
+            || self.rotating
+            || self.selection.is_some()
+            || !self.touches.is_empty()
+    }
+
+    //Changing the count while moving would throw away the old picture every frame, so it only
+    //follows the zoom once the view stops
+    fn auto_iterations(&mut self, stats: Option<[u32; 2]>) {
+        let program = self.state.program();
+        if !program.auto_iters {
+            return;
+        }
+        if let Some(stats) = stats {
+            self.auto_iters.update(self.camera.view(), stats);
+        }
+        let num_iters = self.auto_iters.iterations(self.camera.zoom);
+        if self.is_moving() || num_iters == program.num_iters {
+            return;
+        }
+        self.state
+            .queue_message(Message::NumItersChanged(num_iters));
+        self.window.request_redraw();
+    }
+
+    //Remember where we ended up once the view stops moving
+    fn record_history(&mut self, uniforms: scene::ShaderDataUniforms) {
+        let moving = self.is_moving();
+        let program = self.state.program();
+        let location = history::Location {
+            fractal: program.current_fractal,
+            num_iters: program.num_iters,
+            view: self.camera.view(),
+            rotation: program.rotation,
+        };
+        if moving || self.last_location.is_some_and(|l| l.same_place(&location)) {
+            return;
+        }
+        self.last_location = Some(location);
+        let [width, height] = thumbnail_size(self.size);
+        let pixels = self.scene.capture(
+            &self.device,
+            &self.queue,
+            scene::ShaderDataUniforms {
+                resolution: [width, height],
+                zoom: (self.camera.zoom * f64::from(width) / f64::from(self.size.width)) as f32,
+                ..uniforms
+            },
+            &program.layers,
+        );
+        self.state
+            .queue_message(Message::HistoryRecord(HistoryEntry {
+                location,
+                thumbnail: image::Handle::from_pixels(width, height, pixels),
+            }));
+        self.window.request_redraw();
+    }
+
+    //Bookmarks get their thumbnails one per frame so there's no big freeze
+    fn render_bookmark_thumbnail(&mut self) {
+        let program = self.state.program();
+        let Some((index, bookmark)) = program
+            .bookmarks
+            .iter()
+            .enumerate()
+            .find(|(_, b)| b.thumbnail.is_none())
+        else {
+            return;
+        };
+        let [width, height] = thumbnail_size(self.size);
+        let saved = &bookmark.scene;
+        let pixels = self.scene.capture(
+            &self.device,
+            &self.queue,
+            scene::ShaderDataUniforms {
+                position: saved.view.position.map(|x| x as f32),
+                resolution: [width, height],
+                aspect: width as f32 / height as f32,
+                rotation: saved.rotation.to_radians(),
+                zoom: (saved.view.zoom * f64::from(width) / f64::from(self.size.width)) as f32,
+                fractal: scene::fractal_flags(saved.fractal, saved.smooth_enabled),
+                max_iter: saved.num_iters,
+                msaa: saved.msaa,
+                period_tolerance: if program.period_checking {
+                    program.period_tolerance
+                } else {
+                    0.0
+                },
+                aa_pattern: antialiasing::pattern_flags(program.aa_pattern, program.aa_per_pixel),
+                aa_filter: program.aa_filter as u32,
+                aa_samples: saved.msaa,
+                ..Default::default()
+            },
+            &saved.layers,
+        );
+        let name = bookmark.name.clone();
+        self.state.queue_message(Message::BookmarkThumbnail(
+            index,
+            name,
+            image::Handle::from_pixels(width, height, pixels),
+        ));
+        self.window.request_redraw();
+    }
+}
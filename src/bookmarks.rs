@@ -0,0 +1,239 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use iced_winit::image;
+
+use crate::{
+    coloring::Layer,
+    controls::{Fractals, View},
+    scene_file::{SavedScene, SceneError},
+};
+
+pub const BOOKMARKS_FILE: &str = "bookmarks.fvb";
+const HEADER: &str = "fractal_viewer bookmarks";
+
+#[derive(Clone, Debug)]
+pub struct Bookmark {
+    pub name: String,
+    pub scene: SavedScene,
+    //Rendered by the main loop the first time it's needed
+    pub thumbnail: Option<image::Handle>,
+}
+
+impl Bookmark {
+    pub fn new(name: &str, scene: SavedScene) -> Self {
+        Self {
+            name: name.to_string(),
+            scene,
+            thumbnail: None,
+        }
+    }
+
+    //The shader's y axis points down, so the position is the negated point in the middle
+    fn famous(name: &str, fractal: Fractals, re: f64, im: f64, zoom: f64) -> Self {
+        Self::new(
+            name,
+            SavedScene {
+                fractal,
+                num_iters: 1000,
+                smooth_enabled: true,
+                msaa: 1,
+                view: View {
+                    position: [-re, -im],
+                    zoom,
+                },
+                rotation: 0.0,
+                layers: vec![Layer::default()],
+            },
+        )
+    }
+}
+
+//What you get before adding any of your own
+pub fn defaults() -> Vec<Bookmark> {
+    vec![
+        Bookmark::famous(
+            "Seahorse valley",
+            Fractals::Mandelbrot,
+            -0.7453,
+            0.1127,
+            50000.0,
+        ),
+        Bookmark::famous(
+            "Elephant valley",
+            Fractals::Mandelbrot,
+            0.2925,
+            0.0149,
+            40000.0,
+        ),
+        Bookmark::famous(
+            "Triple spiral valley",
+            Fractals::Mandelbrot,
+            -0.0881,
+            0.6542,
+            30000.0,
+        ),
+        Bookmark::famous(
+            "Scepter valley",
+            Fractals::Mandelbrot,
+            -1.3585,
+            0.0,
+            20000.0,
+        ),
+        Bookmark::famous(
+            "Period 3 mini-brot",
+            Fractals::Mandelbrot,
+            -1.7549,
+            0.0,
+            20000.0,
+        ),
+        Bookmark::famous(
+            "Antenna mini-brot",
+            Fractals::Mandelbrot,
+            -0.1565,
+            1.0322,
+            60000.0,
+        ),
+        Bookmark::famous(
+            "Burning ship armada",
+            Fractals::BurningShip,
+            -1.762,
+            -0.028,
+            15000.0,
+        ),
+    ]
+}
+
+//Missing file means nobody has saved any bookmarks yet, a broken one is worth complaining about
+pub fn load_or_default(path: &Path) -> Result<Vec<Bookmark>, SceneError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(defaults()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+//Moves a file that didn't load out of the way, so saving over it doesn't lose what was in it.
+//Older backups stay too, the name gets a number if .bak is taken
+pub fn back_up(path: &Path) -> std::io::Result<PathBuf> {
+    let backup = (0..1000)
+        .map(|i| {
+            let mut name = path.as_os_str().to_owned();
+            if i > 0 {
+                name.push(format!(".{i}"));
+            }
+            name.push(".bak");
+            PathBuf::from(name)
+        })
+        .find(|backup| !backup.exists())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "too many backups already",
+            )
+        })?;
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+pub fn save(path: &Path, bookmarks: &[Bookmark]) -> Result<(), SceneError> {
+    fs::write(path, serialize(bookmarks))?;
+    Ok(())
+}
+
+pub fn serialize(bookmarks: &[Bookmark]) -> String {
+    let mut out = format!("{HEADER}\n");
+    for bookmark in bookmarks {
+        _ = writeln!(out, "\n[bookmark]\nname = {}", bookmark.name);
+        out.push_str(&bookmark.scene.serialize_body());
+    }
+    out
+}
+
+//Each bookmark is a name followed by a scene without its header
+pub fn parse(contents: &str) -> Result<Vec<Bookmark>, SceneError> {
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next() != Some(HEADER) {
+        return Err(SceneError::Parse("missing header".into()));
+    }
+    let mut chunks: Vec<Vec<&str>> = Vec::new();
+    for line in lines {
+        if line == "[bookmark]" {
+            chunks.push(Vec::new());
+        } else if let Some(chunk) = chunks.last_mut() {
+            chunk.push(line);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            return Err(SceneError::Parse(format!("bad line \"{line}\"")));
+        }
+    }
+    chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut lines = chunk
+                .into_iter()
+                .filter(|l| !l.is_empty() && !l.starts_with('#'));
+            let name = lines
+                .next()
+                .and_then(|l| l.strip_prefix("name"))
+                .and_then(|l| l.trim_start().strip_prefix('='))
+                .ok_or_else(|| SceneError::Parse("bookmark without a name".into()))?;
+            Ok(Bookmark::new(name.trim(), SavedScene::parse_body(lines)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let bookmarks = defaults();
+        let parsed = parse(&serialize(&bookmarks)).unwrap();
+        assert_eq!(parsed.len(), bookmarks.len());
+        for (a, b) in parsed.iter().zip(&bookmarks) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.scene, b.scene);
+        }
+    }
+
+    #[test]
+    fn empty() {
+        assert!(parse(&serialize(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let contents = serialize(&defaults());
+        for broken in [
+            String::new(),
+            contents.replace(HEADER, "fractal_viewer scene"),
+            contents.replacen("name = Seahorse valley", "", 1),
+            contents.replacen("msaa = 1", "msaa = one", 1),
+            format!("{HEADER}\nstray line\n{}", &contents[HEADER.len()..]),
+        ] {
+            assert!(parse(&broken).is_err(), "{broken}");
+        }
+    }
+
+    #[test]
+    fn back_up_moves_the_file() {
+        let dir = std::env::temp_dir().join(format!("fractal_viewer_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(BOOKMARKS_FILE);
+        fs::write(&path, "broken").unwrap();
+        let backup = back_up(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "broken");
+        //A second one doesn't touch the first
+        fs::write(&path, "broken again").unwrap();
+        let second = back_up(&path).unwrap();
+        assert_ne!(second, backup);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "broken");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "broken again");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use crate::controls::View;
+
+//Frames further apart than this are treated as this long, so a hitch doesn't teleport the view
+const MAX_STEP: f32 = 0.1;
+
+//Turns a direction on the screen into a direction on the plane, matching the shader
+pub fn rotate(v: [f32; 2], radians: f32) -> [f32; 2] {
+    let (sin, cos) = radians.sin_cos();
+    [cos * v[0] - sin * v[1], sin * v[0] + cos * v[1]]
+}
+
+//Where we're looking and where we're headed. Moves towards the destination by exponential decay
+//so it eases the same way no matter how often it's redrawn
+pub struct Camera {
+    pub position: [f64; 2],
+    pub zoom: f64,
+    position_dst: [f64; 2],
+    zoom_dst: f64,
+    //Offset from the middle of the window (already rotated onto the plane) that stays put
+    //while zooming, so zooming at the cursor keeps the same point under it
+    zoom_anchor: [f64; 2],
+    //Time in seconds to get about 63% of the way there, 0 jumps straight to the destination
+    pub smoothing: f32,
+    moving: bool,
+}
+
+impl Camera {
+    pub fn new(view: View) -> Self {
+        Self {
+            position: view.position,
+            zoom: view.zoom,
+            position_dst: view.position,
+            zoom_dst: view.zoom,
+            zoom_anchor: [0.0, 0.0],
+            smoothing: 0.075,
+            moving: false,
+        }
+    }
+
+    pub fn view(&self) -> View {
+        View {
+            position: self.position,
+            zoom: self.zoom,
+        }
+    }
+
+    //Point on the plane at an offset from the middle of the window, the offset already rotated
+    //onto the plane
+    pub fn to_plane(&self, offset: [f32; 2]) -> [f64; 2] {
+        [0, 1].map(|i| f64::from(offset[i]) / self.zoom - self.position[i])
+    }
+
+    //The other way around, the offset still has to be rotated back onto the screen
+    pub fn to_offset(&self, point: [f64; 2]) -> [f32; 2] {
+        [0, 1].map(|i| ((point[i] + self.position[i]) * self.zoom) as f32)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.moving
+    }
+
+    fn wake(&mut self) {
+        self.moving = true;
+    }
+
+    pub fn jump_to(&mut self, view: View) {
+        self.position = view.position;
+        self.position_dst = view.position;
+        self.zoom = view.zoom;
+        self.zoom_dst = view.zoom;
+        self.moving = false;
+    }
+
+    pub fn fly_to(&mut self, view: View) {
+        self.position_dst = view.position;
+        self.zoom_dst = view.zoom;
+        self.zoom_anchor = [0.0, 0.0];
+        self.wake();
+    }
+
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        self.position_dst = [
+            self.position_dst[0] + delta[0] as f64,
+            self.position_dst[1] + delta[1] as f64,
+        ];
+        self.wake();
+    }
+
+    pub fn zoom_at(&mut self, factor: f32, anchor: [f32; 2]) {
+        self.zoom_dst *= factor as f64;
+        self.zoom_anchor = anchor.map(f64::from);
+        self.wake();
+    }
+
+    //Advances the animation by dt seconds, returns whether it still has somewhere to go
+    pub fn update(&mut self, dt: f32) -> bool {
+        if !self.moving {
+            return false;
+        }
+        let dt = dt.min(MAX_STEP);
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-dt / self.smoothing).exp()
+        } else {
+            1.0
+        };
+
+        let t = f64::from(t);
+
+        //Moving the destination along with the zoom is what keeps the anchor in place
+        let anchor = self.zoom_anchor;
+        let old_zoom = self.zoom;
+        self.zoom += (self.zoom_dst - self.zoom) * t;
+        for ((position, dst), anchor) in self
+            .position
+            .iter_mut()
+            .zip(self.position_dst.iter_mut())
+            .zip(anchor)
+        {
+            *dst += anchor / self.zoom - anchor / old_zoom;
+            *position += (*dst - *position) * t;
+        }
+
+        //Relative so it still settles when zoomed in really far, and half a pixel is close enough
+        let distance = ((self.position[0] - self.position_dst[0]).powi(2)
+            + (self.position[1] - self.position_dst[1]).powi(2))
+        .sqrt();
+        if (self.zoom_dst / self.zoom - 1.0).abs() < 1e-4 && distance * self.zoom < 0.5 {
+            self.zoom = self.zoom_dst;
+            self.position = self.position_dst;
+            self.moving = false;
+        }
+        self.moving
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moving_camera() -> Camera {
+        let mut camera = Camera::new(View {
+            position: [0.0, 0.0],
+            zoom: 500.0,
+        });
+        camera.pan([1.0, -2.0]);
+        camera.zoom_at(4.0, [0.0, 0.0]);
+        camera
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-4 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn steps_add_up() {
+        let mut once = moving_camera();
+        let mut twice = moving_camera();
+        once.update(0.032);
+        twice.update(0.016);
+        twice.update(0.016);
+        assert!(close(once.zoom, twice.zoom));
+        assert!(close(once.position[0], twice.position[0]));
+        assert!(close(once.position[1], twice.position[1]));
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut capped = moving_camera();
+        let mut max = moving_camera();
+        capped.update(5.0);
+        max.update(MAX_STEP);
+        assert_eq!(capped.view(), max.view());
+        assert!(capped.is_moving());
+    }
+
+    #[test]
+    fn no_smoothing_jumps() {
+        let mut camera = moving_camera();
+        camera.smoothing = 0.0;
+        assert!(!camera.update(0.0));
+        assert_eq!(
+            camera.view(),
+            View {
+                position: [1.0, -2.0],
+                zoom: 2000.0,
+            }
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::palette::{CosinePalette, Palette, PaletteMode, Preset};
+
+//Maximum number of coloring layers the shader can handle
+pub const MAX_LAYERS: usize = 8;
+//Size of a layer in the layer buffer, in 4 byte words
+pub const LAYER_SIZE: usize = 28;
+
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Algorithm {
+    #[default]
+    EscapeTime = 0,
+    DistanceEstimate = 1,
+    OrbitTrap = 2,
+    StripeAverage = 3,
+    Lighting = 4,
+    TriangleInequality = 5,
+    Curvature = 6,
+    //Colors the inside by the length of the cycle each point fell into
+    Period = 7,
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EscapeTime => write!(f, "Escape time"),
+            Self::DistanceEstimate => write!(f, "Distance estimate"),
+            Self::OrbitTrap => write!(f, "Orbit trap"),
+            Self::StripeAverage => write!(f, "Stripe average"),
+            Self::Lighting => write!(f, "Lighting"),
+            Self::TriangleInequality => write!(f, "Triangle inequality average"),
+            Self::Curvature => write!(f, "Curvature average"),
+            Self::Period => write!(f, "Interior period"),
+        }
+    }
+}
+
+//Applied to the value of the coloring algorithm before looking up the palette
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Transfer {
+    #[default]
+    Linear = 0,
+    Sqrt = 1,
+    CubeRoot = 2,
+    Log = 3,
+    Square = 4,
+}
+
+impl std::fmt::Display for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linear => write!(f, "Linear"),
+            Self::Sqrt => write!(f, "Square root"),
+            Self::CubeRoot => write!(f, "Cube root"),
+            Self::Log => write!(f, "Log"),
+            Self::Square => write!(f, "Square"),
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum BlendMode {
+    #[default]
+    Normal = 0,
+    Multiply = 1,
+    Screen = 2,
+    Overlay = 3,
+    Add = 4,
+}
+
+impl std::fmt::Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Normal => write!(f, "Normal"),
+            Self::Multiply => write!(f, "Multiply"),
+            Self::Screen => write!(f, "Screen"),
+            Self::Overlay => write!(f, "Overlay"),
+            Self::Add => write!(f, "Add"),
+        }
+    }
+}
+
+impl Algorithm {
+    pub const ALL: [Self; 8] = [
+        Self::EscapeTime,
+        Self::DistanceEstimate,
+        Self::OrbitTrap,
+        Self::StripeAverage,
+        Self::TriangleInequality,
+        Self::Curvature,
+        Self::Lighting,
+        Self::Period,
+    ];
+}
+
+impl Transfer {
+    pub const ALL: [Self; 5] = [
+        Self::Linear,
+        Self::Sqrt,
+        Self::CubeRoot,
+        Self::Log,
+        Self::Square,
+    ];
+}
+
+impl BlendMode {
+    pub const ALL: [Self; 5] = [
+        Self::Normal,
+        Self::Multiply,
+        Self::Screen,
+        Self::Overlay,
+        Self::Add,
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub algorithm: Algorithm,
+    pub transfer: Transfer,
+    pub blend: BlendMode,
+    pub opacity: f32,
+    //Only used by the stripe average for now, as the stripe frequency
+    pub param: f32,
+    pub num_colors: u32,
+    pub palette: Palette,
+    pub palette_mode: PaletteMode,
+    pub cosine: CosinePalette,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::EscapeTime,
+            transfer: Transfer::Linear,
+            blend: BlendMode::Normal,
+            opacity: 1.0,
+            param: 5.0,
+            num_colors: 200,
+            palette: Preset::TransFlag.palette(),
+            palette_mode: PaletteMode::Stops,
+            cosine: CosinePalette::DEFAULT,
+        }
+    }
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.algorithm, self.blend)
+    }
+}
+
+impl Layer {
+    //Layout matches the Layer struct in the fragment shader, stop_offset is where this layer's
+    //colors start in the color buffer
+    pub fn to_raw(&self, stop_offset: u32) -> [u32; LAYER_SIZE] {
+        let mut data = [0; LAYER_SIZE];
+        data[..8].copy_from_slice(&[
+            self.algorithm as u32,
+            self.transfer as u32,
+            self.blend as u32,
+            self.opacity.to_bits(),
+            self.palette_mode as u32,
+            self.num_colors,
+            stop_offset,
+            self.palette.colors.len() as u32,
+        ]);
+        data[8] = self.param.to_bits();
+        //Vec4s need to be aligned to 16 bytes, so the cosine palette starts at 12
+        for (i, param) in self.cosine.raw().iter().enumerate() {
+            for (j, value) in param.iter().enumerate() {
+                data[12 + i * 4 + j] = value.to_bits();
+            }
+        }
+        data
+    }
+}
+
+//Packs the layer stack into the layer buffer and the color stop buffer
+pub fn layers_raw(layers: &[Layer]) -> (Vec<u32>, Vec<f32>) {
+    let mut raw_layers = Vec::with_capacity(layers.len() * LAYER_SIZE);
+    let mut raw_colors = Vec::new();
+    for layer in layers.iter().take(MAX_LAYERS) {
+        raw_layers.extend(layer.to_raw((raw_colors.len() / crate::scene::STOP_SIZE) as u32));
+        for (c, p) in layer.palette.colors.iter().zip(&layer.palette.positions) {
+            //Interpolated and blended in linear space, the picker works in sRGB
+            let [r, g, b, a] = c.into_linear();
+            raw_colors.extend([r, g, b, a, *p, 0.0, 0.0, 0.0]);
+        }
+    }
+    (raw_layers, raw_colors)
+}
+
+//The parts of the layer stack that change what gets computed for each orbit rather than just
+//how it's colored
+pub fn orbit_params(layers: &[Layer]) -> Vec<u32> {
+    layers
+        .iter()
+        .take(MAX_LAYERS)
+        .flat_map(|layer| [layer.algorithm as u32, layer.param.to_bits()])
+        .collect()
+}
@@ -1,5 +1,6 @@
 use std::{
     ops::RangeInclusive,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
@@ -7,8 +8,24 @@ use iced_aw::ColorPicker;
 use iced_wgpu::Color;
 use iced_winit::{
     alignment, column, row, theme,
-    widget::{button, checkbox, column, container, pick_list, scrollable, slider, text},
-    Command, Length, Program,
+    widget::{
+        button, checkbox, column, container, image, pick_list, scrollable, slider, text,
+        text_input, tooltip, vertical_space, Space,
+    },
+    Command, Element, Length, Program,
+};
+
+use crate::{
+    antialiasing::{Filter, SamplePattern},
+    bookmarks::{self, Bookmark, BOOKMARKS_FILE},
+    coloring::{Algorithm, BlendMode, Layer, Transfer, MAX_LAYERS},
+    history::{History, HistoryEntry, Location},
+    iterations::MAX_ITERATIONS,
+    keybindings::{KeyBindings, KEYBINDINGS_FILE},
+    minimap,
+    palette::{self, color_hex, CosinePalette, LibraryEntry, Palette, PaletteMode, Preset},
+    scene_file::SavedScene,
+    screenshot::ExportFormat,
 };
 
 #[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
@@ -21,6 +38,21 @@ pub enum Fractals {
     Eye = 16,
 }
 
+impl Fractals {
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|f| *f == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub const ALL: [Self; 5] = [
+        Self::Mandelbrot,
+        Self::BurningShip,
+        Self::Tricorn,
+        Self::Feather,
+        Self::Eye,
+    ];
+}
+
 impl std::fmt::Display for Fractals {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -33,18 +65,133 @@ impl std::fmt::Display for Fractals {
     }
 }
 
+//Zoom the viewer starts at, counts as 1x magnification
+pub const BASE_ZOOM: f64 = 500.0;
+
+//Most samples per pixel the slider goes to
+pub const MAX_MSAA: u32 = 8;
+
+//Significant digits of a coordinate the view is sure to keep, the readouts stop there
+const SIGNIFICANT_DIGITS: i32 = f64::DIGITS as i32;
+
+//Enough decimals to tell neighbouring pixels apart, but none past what the float holds
+fn decimals(value: f64, zoom: f64) -> usize {
+    let pixels = (zoom.log10().ceil() as i32 + 1).max(2);
+    let magnitude = if value == 0.0 {
+        0
+    } else {
+        value.abs().log10().floor() as i32
+    };
+    pixels.min(SIGNIFICANT_DIGITS - 1 - magnitude).max(0) as usize
+}
+
+//Digits in a number from the first one that isn't a zero, leaving out the exponent
+fn significant_digits(text: &str) -> usize {
+    let mantissa = text.trim().split(['e', 'E']).next().unwrap_or("");
+    mantissa
+        .chars()
+        .filter(char::is_ascii_digit)
+        .skip_while(|&c| c == '0')
+        .count()
+}
+
+//Takes as many digits as you want to give it so locations can be pasted from anywhere, they get
+//rounded to what a 64 bit float holds on the way into the view
+fn parse_coordinate(text: &str) -> Result<Option<f64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .map(Some)
+        .ok_or_else(|| format!("\"{text}\" is not a number"))
+}
+
+//Kept in 64 bit floats so typed in and saved locations come back exactly, the shader only gets
+//32 bits of it
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct View {
+    pub position: [f64; 2],
+    pub zoom: f64,
+}
+
 #[derive(Default, Clone)]
 pub struct Controls {
     ui_open: bool,
     pub current_fractal: Fractals,
-    pub colors: Vec<Color>,
+    pub layers: Vec<Layer>,
     pub num_iters: u32,
-    pub num_colors: u32,
+    //Lets the app pick num_iters from the zoom and how much of the picture runs out of them
+    pub auto_iters: bool,
     pub smooth_enabled: bool,
     pub msaa: u32,
+    pub aa_pattern: SamplePattern,
+    pub aa_filter: Filter,
+    //Moves the pattern around by a different random amount in every pixel
+    pub aa_per_pixel: bool,
+    //Only gives pixels on edges all the samples, the threshold is how different the colors
+    //around a pixel have to be
+    pub adaptive_aa: bool,
+    pub adaptive_threshold: f32,
+    //Adds the anti aliasing samples over a few frames instead of all at once, and drops the
+    //resolution while moving
+    pub progressive: bool,
+    //Stops iterating points that fell into a cycle, tolerance is how close z has to come back
+    pub period_checking: bool,
+    pub period_tolerance: f32,
+    //In degrees, counterclockwise
+    pub rotation: f32,
+    //Easing for panning and zooming, in seconds
+    pub smooth_navigation: bool,
+    pub smoothing: f32,
+    //How much one scroll notch or key press zooms in
+    pub zoom_factor: f32,
+    //Picked up by the main loop on the next frame
     pub pending_screenshot: Arc<Mutex<bool>>,
+    pub export_format: ExportFormat,
+    //Updated by the main loop every frame
+    pub view: Arc<Mutex<View>>,
+    //Set when a scene is loaded, picked up by the main loop
+    pub pending_view: Arc<Mutex<Option<View>>>,
+    //Same but animated
+    pub pending_flight: Arc<Mutex<Option<View>>>,
+    //Point on the plane under the mouse, updated by the main loop
+    pub cursor_coordinate: Arc<Mutex<Option<[f64; 2]>>>,
+    pub show_minimap: bool,
+    //Zoom to show next to the minimap once the view is too small to outline on it, set by the
+    //main loop
+    pub minimap_depth: Arc<Mutex<Option<f64>>>,
+    //Draws the orbit of the point under the cursor
+    pub show_orbit: bool,
+    //What happened to that point, set by the main loop
+    pub orbit_summary: Arc<Mutex<Option<String>>>,
+    re_text: String,
+    im_text: String,
+    magnification_text: String,
+    location_status: String,
+    pub bookmarks: Vec<Bookmark>,
+    bookmark_name: String,
+    bookmark_status: String,
+    //Off when the bookmarks file couldn't be read or moved aside
+    bookmarks_saving: bool,
+    pub keybindings: KeyBindings,
+    show_help: bool,
+    history: History,
+    current_layer: usize,
     color_editing_index: usize,
     editing_color: bool,
+    palette_path: String,
+    palette_library: Vec<LibraryEntry>,
+    palette_status: String,
+    palette_seed: String,
+    cosine_text: String,
+    rotation_text: String,
+    //Typing goes past the slider's range
+    num_iters_text: String,
+    scene_path: String,
+    scene_status: String,
 }
 
 #[derive(Debug, Clone)]
@@ -53,49 +200,553 @@ pub enum Message {
     ChangeFractal(Fractals),
     NumColorsChanged(u32),
     NumItersChanged(u32),
+    NumItersTextChanged(String),
+    ToggleAutoIters(bool),
     ToggleSmooth(bool),
     MsaaChanged(u32),
+    AaPatternChanged(SamplePattern),
+    AaFilterChanged(Filter),
+    ToggleAaPerPixel(bool),
+    ToggleAdaptiveAa(bool),
+    AdaptiveThresholdChanged(f32),
+    ZoomFactorChanged(f32),
+    ToggleSmoothNavigation(bool),
+    ToggleMinimap(bool),
+    ToggleProgressive(bool),
+    TogglePeriodChecking(bool),
+    PeriodToleranceChanged(f32),
+    ToggleOrbit(bool),
+    SmoothingChanged(f32),
+    RotationChanged(f32),
+    RotateBy(f32),
+    RotationTextChanged(String),
     ColorRemove(usize),
     OpenColorPicker(usize),
     ColorAdd,
     CancelColor,
     SubmitColor(Color),
-    // ScreenshotClick,
-}
-
-fn color_raw(color: &Color) -> Vec<f32> {
-    vec![color.r, color.g, color.b, color.a]
-}
-
-fn color_hex(color: &Color) -> String {
-    let r_hex = format!("{:02x}", (color.r * 255.0) as u8);
-    let g_hex = format!("{:02x}", (color.g * 255.0) as u8);
-    let b_hex = format!("{:02x}", (color.b * 255.0) as u8);
-    let a_hex = format!("{:02x}", (color.a * 255.0) as u8);
-
-    format!("#{}{}{}{}", r_hex, g_hex, b_hex, a_hex)
+    PalettePathChanged(String),
+    ImportPalette,
+    ExportPalette,
+    LibraryPaletteSelected(LibraryEntry),
+    PresetSelected(Preset),
+    PaletteSeedChanged(String),
+    RandomizePalette,
+    GenerateFromSeed,
+    PaletteModeChanged(PaletteMode),
+    //Parameter index (a, b, c, d), channel, value
+    CosineChanged(usize, usize, f32),
+    CosineTextChanged(String),
+    LayerAdd,
+    LayerRemove(usize),
+    LayerMoveUp(usize),
+    LayerSelected(usize),
+    AlgorithmChanged(Algorithm),
+    TransferChanged(Transfer),
+    BlendChanged(BlendMode),
+    OpacityChanged(f32),
+    ParamChanged(f32),
+    ScenePathChanged(String),
+    SaveScene,
+    LoadScene,
+    ScreenshotClick,
+    ExportFormatChanged(ExportFormat),
+    ToggleHelp,
+    //Sent by the main loop when the view stops moving
+    HistoryRecord(HistoryEntry),
+    HistoryBack,
+    HistoryForward,
+    HistorySelected(usize),
+    ReTextChanged(String),
+    ImTextChanged(String),
+    MagnificationTextChanged(String),
+    GoToCoordinates,
+    CopyLocation,
+    BookmarkNameChanged(String),
+    AddBookmark,
+    RemoveBookmark(usize),
+    BookmarkSelected(usize),
+    //Index and name, in case the list changed while it was rendering
+    BookmarkThumbnail(usize, String, iced_winit::image::Handle),
 }
 
 impl Controls {
     pub fn new() -> Self {
+        //A file that didn't load gets moved aside before anything can be saved over it, if that
+        //doesn't work either nothing gets saved at all
+        let path = Path::new(BOOKMARKS_FILE);
+        let (bookmarks, bookmark_status, bookmarks_saving) = match bookmarks::load_or_default(path)
+        {
+            Ok(bookmarks) => (bookmarks, String::new(), true),
+            Err(e) => match bookmarks::back_up(path) {
+                Ok(backup) => (
+                    bookmarks::defaults(),
+                    format!("{e}, moved the old file to {}", backup.display()),
+                    true,
+                ),
+                Err(backup_error) => (
+                    bookmarks::defaults(),
+                    format!(
+                        "{e}, not saving bookmarks so it doesn't get overwritten ({backup_error})"
+                    ),
+                    false,
+                ),
+            },
+        };
         Self {
-            //The trans flag colors uwu 🏳️‍⚧️
-            colors: vec![
-                Color::from_rgb(85.0 / 255.0, 205.0 / 255.0, 252.0 / 255.0),
-                Color::from_rgb(247.0 / 255.0, 168.0 / 255.0, 184.0 / 255.0),
-                Color::from_rgb(1.0, 1.0, 1.0),
-                Color::from_rgb(247.0 / 255.0, 168.0 / 255.0, 184.0 / 255.0),
-                Color::from_rgb(85.0 / 255.0, 205.0 / 255.0, 252.0 / 255.0),
-            ],
+            bookmarks,
+            bookmark_status,
+            bookmarks_saving,
+            layers: vec![Layer::default()],
             num_iters: 1000,
-            num_colors: 200,
+            num_iters_text: "1000".into(),
             msaa: 1,
+            aa_per_pixel: true,
+            adaptive_threshold: 0.02,
+            progressive: true,
+            period_checking: true,
+            period_tolerance: 1e-5,
+            zoom_factor: 1.2,
+            smooth_navigation: true,
+            smoothing: 0.075,
+            show_minimap: true,
+            rotation_text: "0".into(),
+            view: Arc::new(Mutex::new(View {
+                position: [0.0, 0.0],
+                zoom: BASE_ZOOM,
+            })),
+            palette_path: format!("{}/palette.fvp", palette::PALETTE_DIR),
+            palette_library: palette::scan_library(),
+            palette_seed: palette::new_seed().to_string(),
+            cosine_text: CosinePalette::DEFAULT.to_string(),
+            scene_path: "scene.fvs".into(),
+            keybindings: KeyBindings::load_or_default(Path::new(KEYBINDINGS_FILE)),
             ..Default::default()
         }
     }
 
-    pub fn get_colors_raw(&self) -> Vec<f32> {
-        self.colors.clone().iter().flat_map(color_raw).collect()
+    fn layer(&self) -> &Layer {
+        &self.layers[self.current_layer]
+    }
+
+    fn layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.current_layer]
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.layer_mut().palette = palette;
+        self.editing_color = false;
+    }
+
+    fn load_palette(&mut self, path: &Path) {
+        match Palette::load(path) {
+            Ok(p) => {
+                self.palette_status = format!("Loaded {}", p.name);
+                self.set_palette(p);
+            }
+            Err(e) => self.palette_status = e.to_string(),
+        }
+    }
+
+    fn generate_palette(&mut self) {
+        match self.palette_seed.trim().parse::<u64>() {
+            Ok(seed) => {
+                let random = CosinePalette::random(seed);
+                match self.layer().palette_mode {
+                    PaletteMode::Stops => {
+                        self.set_palette(random.to_palette(&format!("Random {seed}"), 8))
+                    }
+                    PaletteMode::Cosine => self.set_cosine(random),
+                }
+                self.palette_status.clear();
+            }
+            Err(_) => self.palette_status = "The seed must be a positive number".into(),
+        }
+    }
+
+    fn set_num_iters(&mut self, num_iters: u32) {
+        self.num_iters = num_iters.clamp(1, MAX_ITERATIONS);
+        self.num_iters_text = self.num_iters.to_string();
+    }
+
+    fn set_rotation(&mut self, degrees: f32) {
+        //Keep it between -180 and 180 so the number stays readable after spinning around a lot
+        self.rotation = (degrees + 180.0).rem_euclid(360.0) - 180.0;
+        self.rotation_text = format!("{:.1}", self.rotation);
+    }
+
+    fn go_to_location(&mut self, location: Option<Location>) {
+        if let Some(location) = location {
+            self.current_fractal = location.fractal;
+            self.set_num_iters(location.num_iters);
+            self.set_rotation(location.rotation);
+            *self.pending_view.lock().unwrap() = Some(location.view);
+        }
+    }
+
+    fn set_cosine(&mut self, cosine: CosinePalette) {
+        self.layer_mut().cosine = cosine;
+        self.cosine_text = cosine.to_string();
+    }
+
+    fn select_layer(&mut self, index: usize) {
+        self.current_layer = index;
+        self.editing_color = false;
+        self.cosine_text = self.layer().cosine.to_string();
+    }
+
+    fn add_color(&mut self) {
+        let palette = &mut self.layer_mut().palette;
+        if palette.colors.len() >= crate::scene::MAX_COLORS {
+            return;
+        }
+        palette.colors.push(Color::from_rgb(1.0, 1.0, 1.0));
+        if palette::is_even(&palette.positions) {
+            palette.positions = palette::even_positions(palette.colors.len());
+        } else {
+            //Put the new color halfway between the last one and the end of the palette
+            let last = palette.positions.last().copied().unwrap_or(0.0);
+            palette.positions.push((last + 1.0) * 0.5);
+        }
+    }
+
+    fn remove_color(&mut self, index: usize) {
+        let palette = &mut self.layer_mut().palette;
+        //The shader needs at least one color
+        if palette.colors.len() == 1 {
+            return;
+        }
+        let even = palette::is_even(&palette.positions);
+        palette.colors.remove(index);
+        palette.positions.remove(index);
+        if even {
+            palette.positions = palette::even_positions(palette.colors.len());
+        }
+    }
+
+    fn saved_scene(&self) -> SavedScene {
+        SavedScene {
+            fractal: self.current_fractal,
+            num_iters: self.num_iters,
+            smooth_enabled: self.smooth_enabled,
+            msaa: self.msaa,
+            view: *self.view.lock().unwrap(),
+            rotation: self.rotation,
+            layers: self.layers.clone(),
+        }
+    }
+
+    //Empty fields keep the current value, gives back a note when digits got dropped
+    fn go_to_coordinates(&mut self) -> Result<String, String> {
+        let mut view = *self.view.lock().unwrap();
+        if let Some(re) = parse_coordinate(&self.re_text)? {
+            view.position[0] = -re;
+        }
+        if let Some(im) = parse_coordinate(&self.im_text)? {
+            view.position[1] = -im;
+        }
+        if let Some(magnification) = parse_coordinate(&self.magnification_text)? {
+            if magnification <= 0.0 {
+                return Err("The magnification has to be positive".into());
+            }
+            view.zoom = magnification * BASE_ZOOM;
+        }
+        *self.pending_view.lock().unwrap() = Some(view);
+        let digits = significant_digits(&self.re_text).max(significant_digits(&self.im_text));
+        Ok(if digits > SIGNIFICANT_DIGITS as usize {
+            format!("Rounded to {SIGNIFICANT_DIGITS} significant digits, that's all the view keeps")
+        } else {
+            String::new()
+        })
+    }
+
+    //Every digit the view has, so pasting it back gets exactly here
+    fn location_text(&self) -> String {
+        let view = *self.view.lock().unwrap();
+        let [re, im] = view.position.map(|x| -x);
+        format!(
+            "fractal = {}\nre = {re}\nim = {im}\nmagnification = {:e}\nrotation = {}",
+            self.current_fractal,
+            view.zoom / BASE_ZOOM,
+            self.rotation,
+        )
+    }
+
+    fn save_bookmarks(&mut self) {
+        if !self.bookmarks_saving {
+            return;
+        }
+        self.bookmark_status = match bookmarks::save(Path::new(BOOKMARKS_FILE), &self.bookmarks) {
+            Ok(_) => String::new(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn apply_scene(&mut self, scene: SavedScene) {
+        self.current_fractal = scene.fractal;
+        self.set_num_iters(scene.num_iters);
+        self.smooth_enabled = scene.smooth_enabled;
+        self.msaa = scene.msaa;
+        self.set_rotation(scene.rotation);
+        self.layers = scene.layers;
+        self.select_layer(0);
+        *self.pending_view.lock().unwrap() = Some(scene.view);
+    }
+}
+
+impl Controls {
+    fn cosine_view(&self) -> iced_winit::widget::Column<'_, Message, iced_wgpu::Renderer> {
+        let params = self
+            .layer()
+            .cosine
+            .raw()
+            .into_iter()
+            .zip(CosinePalette::RANGES)
+            .enumerate()
+            .map(|(param, (values, range))| {
+                let mut sliders = row![text(["a", "b", "c", "d"][param]).width(15)].spacing(5);
+                for (channel, value) in values.into_iter().enumerate() {
+                    sliders = sliders.push(
+                        slider(range.clone(), value, move |v| {
+                            Message::CosineChanged(param, channel, v)
+                        })
+                        .step(0.01),
+                    );
+                }
+                Element::from(sliders)
+            })
+            .collect();
+        column![
+            text("a + b * cos(2π(c * t + d))"),
+            column(params).spacing(5),
+            text_input("12 numbers", &self.cosine_text, Message::CosineTextChanged),
+            text(&self.palette_status).size(14),
+        ]
+        .spacing(10)
+    }
+
+    fn layers_view(&self) -> iced_winit::widget::Column<'_, Message, iced_wgpu::Renderer> {
+        let small_button = |label| {
+            button(text(label).horizontal_alignment(alignment::Horizontal::Center))
+                .height(30)
+                .width(30)
+        };
+        let mut label = row![text("Layers")].spacing(5);
+        if self.layers.len() < MAX_LAYERS {
+            label = label.push(small_button("+").on_press(Message::LayerAdd));
+        }
+        let layers = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(i, layer)| {
+                let name = if i == self.current_layer {
+                    format!("> {layer}")
+                } else {
+                    layer.to_string()
+                };
+                let mut row = row![button(text(name).size(14))
+                    .on_press(Message::LayerSelected(i))
+                    .width(Length::Fill)]
+                .spacing(5);
+                if i > 0 {
+                    row = row.push(small_button("^").on_press(Message::LayerMoveUp(i)));
+                }
+                if self.layers.len() > 1 {
+                    row = row.push(small_button("X").on_press(Message::LayerRemove(i)));
+                }
+                Element::from(row)
+            })
+            .collect();
+        let layer = self.layer();
+        let column = column![
+            label,
+            column(layers).spacing(5),
+            pick_list(
+                &Algorithm::ALL[..],
+                Some(layer.algorithm),
+                Message::AlgorithmChanged
+            ),
+            pick_list(
+                &Transfer::ALL[..],
+                Some(layer.transfer),
+                Message::TransferChanged
+            ),
+            pick_list(
+                &BlendMode::ALL[..],
+                Some(layer.blend),
+                Message::BlendChanged
+            ),
+            text("Opacity"),
+            slider(0.0..=1.0, layer.opacity, Message::OpacityChanged).step(0.01),
+        ]
+        .spacing(10);
+        if layer.algorithm == Algorithm::StripeAverage {
+            column
+                .push(text(format!("Stripe frequency {}", layer.param)))
+                .push(slider(1.0..=20.0, layer.param, Message::ParamChanged).step(0.5))
+        } else {
+            column
+        }
+    }
+}
+
+impl Controls {
+    fn history_view(&self) -> iced_winit::widget::Column<'_, Message, iced_wgpu::Renderer> {
+        let mut back = button("Back");
+        if self.history.can_go_back() {
+            back = back.on_press(Message::HistoryBack);
+        }
+        let mut forward = button("Forward");
+        if self.history.can_go_forward() {
+            forward = forward.on_press(Message::HistoryForward);
+        }
+        //Newest first
+        let entries = self
+            .history
+            .entries()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, entry)| {
+                let label = format!(
+                    "{}{}\nx{:.3e}",
+                    if i == self.history.current() {
+                        "> "
+                    } else {
+                        ""
+                    },
+                    entry.location.fractal,
+                    entry.location.view.zoom / BASE_ZOOM
+                );
+                button(
+                    row![
+                        image(entry.thumbnail.clone()).width(64),
+                        text(label).size(14)
+                    ]
+                    .spacing(5),
+                )
+                .on_press(Message::HistorySelected(i))
+                .width(Length::Fill)
+                .into()
+            })
+            .collect();
+        column![
+            row![text("History"), back, forward].spacing(5),
+            column(entries).spacing(5),
+        ]
+        .spacing(10)
+    }
+
+    fn location_view(&self) -> iced_winit::widget::Column<'_, Message, iced_wgpu::Renderer> {
+        let view = *self.view.lock().unwrap();
+        let cursor = match *self.cursor_coordinate.lock().unwrap() {
+            Some([re, im]) => format!(
+                "Cursor {re:.0$} {im:+.1$}i",
+                decimals(re, view.zoom),
+                decimals(im, view.zoom)
+            ),
+            None => String::new(),
+        };
+        let [re, im] = view.position.map(|x| -x);
+        let field = |label, placeholder, value, message: fn(String) -> Message| {
+            row![
+                text(label).width(40),
+                text_input(placeholder, value, message).on_submit(Message::GoToCoordinates),
+            ]
+            .spacing(5)
+        };
+        column![
+            text("Location"),
+            text(format!(
+                "Center {re:.0$} {im:+.1$}i",
+                decimals(re, view.zoom),
+                decimals(im, view.zoom)
+            ))
+            .size(14),
+            text(format!("Magnification {:.3e}", view.zoom / BASE_ZOOM)).size(14),
+            text(cursor).size(14),
+            field("Re", "Real", &self.re_text, Message::ReTextChanged),
+            field("Im", "Imaginary", &self.im_text, Message::ImTextChanged),
+            field(
+                "Zoom",
+                "Magnification",
+                &self.magnification_text,
+                Message::MagnificationTextChanged
+            ),
+            row![
+                button("Go").on_press(Message::GoToCoordinates),
+                button("Copy location").on_press(Message::CopyLocation),
+            ]
+            .spacing(5),
+            text(&self.location_status).size(14),
+        ]
+        .spacing(5)
+    }
+
+    fn bookmarks_view(&self) -> iced_winit::widget::Column<'_, Message, iced_wgpu::Renderer> {
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .enumerate()
+            .map(|(i, bookmark)| {
+                let thumbnail: Element<'_, Message, iced_wgpu::Renderer> = match &bookmark.thumbnail
+                {
+                    Some(handle) => image(handle.clone()).width(64).into(),
+                    None => text("...").width(64).into(),
+                };
+                row![
+                    button(row![thumbnail, text(&bookmark.name).size(14)].spacing(5))
+                        .on_press(Message::BookmarkSelected(i))
+                        .width(Length::Fill),
+                    button(text("X").horizontal_alignment(alignment::Horizontal::Center))
+                        .on_press(Message::RemoveBookmark(i))
+                        .width(30)
+                        .height(30),
+                ]
+                .spacing(5)
+                .into()
+            })
+            .collect();
+        column![
+            text("Bookmarks"),
+            row![
+                text_input("Name", &self.bookmark_name, Message::BookmarkNameChanged)
+                    .on_submit(Message::AddBookmark),
+                button("Add").on_press(Message::AddBookmark),
+            ]
+            .spacing(5),
+            text(&self.bookmark_status).size(14),
+            column(bookmarks).spacing(5),
+        ]
+        .spacing(10)
+    }
+
+    fn help_view(&self) -> Element<'_, Message, iced_wgpu::Renderer> {
+        let bindings = self
+            .keybindings
+            .bindings
+            .iter()
+            .map(|(action, bindings)| {
+                row![
+                    text(action.to_string()).size(16).width(150),
+                    text(KeyBindings::describe(bindings)).size(16),
+                ]
+                .into()
+            })
+            .collect();
+        container(
+            column![
+                text("Keyboard shortcuts"),
+                column(bindings).spacing(5),
+                text(format!("Edit {KEYBINDINGS_FILE} to change them")).size(14),
+                button("Close").on_press(Message::ToggleHelp),
+            ]
+            .spacing(10),
+        )
+        .padding(15)
+        .style(theme::Container::Custom(Box::new(crate::theme::Panel)))
+        .into()
     }
 }
 
@@ -108,12 +759,40 @@ impl Program for Controls {
         match message {
             Message::ToggleUi => self.ui_open = !self.ui_open,
             Message::ChangeFractal(f) => self.current_fractal = f,
-            Message::NumColorsChanged(value) => self.num_colors = value,
-            Message::NumItersChanged(value) => self.num_iters = value,
+            Message::NumColorsChanged(value) => self.layer_mut().num_colors = value,
+            Message::NumItersChanged(value) => self.set_num_iters(value),
+            Message::NumItersTextChanged(text) => {
+                if let Ok(value) = text.trim().parse::<u32>() {
+                    self.num_iters = value.clamp(1, MAX_ITERATIONS);
+                }
+                self.num_iters_text = text;
+            }
+            Message::ToggleAutoIters(value) => self.auto_iters = value,
             Message::ToggleSmooth(value) => self.smooth_enabled = value,
             Message::MsaaChanged(value) => self.msaa = value,
-            Message::ColorRemove(index) => _ = self.colors.remove(index),
-            Message::ColorAdd => self.colors.push(Color::from_rgb(1.0, 1.0, 1.0)),
+            Message::AaPatternChanged(value) => self.aa_pattern = value,
+            Message::AaFilterChanged(value) => self.aa_filter = value,
+            Message::ToggleAaPerPixel(value) => self.aa_per_pixel = value,
+            Message::ToggleAdaptiveAa(value) => self.adaptive_aa = value,
+            Message::AdaptiveThresholdChanged(value) => self.adaptive_threshold = value,
+            Message::ZoomFactorChanged(value) => self.zoom_factor = value,
+            Message::ToggleSmoothNavigation(value) => self.smooth_navigation = value,
+            Message::ToggleMinimap(value) => self.show_minimap = value,
+            Message::ToggleProgressive(value) => self.progressive = value,
+            Message::TogglePeriodChecking(value) => self.period_checking = value,
+            Message::PeriodToleranceChanged(value) => self.period_tolerance = value,
+            Message::ToggleOrbit(value) => self.show_orbit = value,
+            Message::SmoothingChanged(value) => self.smoothing = value,
+            Message::RotationChanged(value) => self.set_rotation(value),
+            Message::RotateBy(delta) => self.set_rotation(self.rotation + delta),
+            Message::RotationTextChanged(text) => {
+                if let Ok(value) = text.trim().parse::<f32>() {
+                    self.rotation = value;
+                }
+                self.rotation_text = text;
+            }
+            Message::ColorRemove(index) => self.remove_color(index),
+            Message::ColorAdd => self.add_color(),
             Message::OpenColorPicker(index) => {
                 self.color_editing_index = index;
                 self.editing_color = true;
@@ -121,8 +800,149 @@ impl Program for Controls {
             Message::CancelColor => self.editing_color = false,
             Message::SubmitColor(color) => {
                 self.editing_color = false;
-                self.colors[self.color_editing_index] = color;
-            } // Message::ScreenshotClick => *self.pending_screenshot.lock().unwrap() = true,
+                let index = self.color_editing_index;
+                self.layer_mut().palette.colors[index] = color;
+            }
+            Message::PalettePathChanged(path) => self.palette_path = path,
+            Message::ImportPalette => self.load_palette(&PathBuf::from(&self.palette_path)),
+            Message::ExportPalette => {
+                let path = Path::new(&self.palette_path);
+                if let Some(dir) = path.parent() {
+                    _ = std::fs::create_dir_all(dir);
+                }
+                self.palette_status = match self.layer().palette.save(path) {
+                    Ok(_) => format!("Saved {}", self.palette_path),
+                    Err(e) => e.to_string(),
+                };
+                //The palette may have been saved into the library
+                self.palette_library = palette::scan_library();
+            }
+            Message::LibraryPaletteSelected(entry) => {
+                self.palette_path = entry.path.to_string_lossy().to_string();
+                self.load_palette(&entry.path);
+            }
+            Message::PresetSelected(preset) => self.set_palette(preset.palette()),
+            Message::PaletteSeedChanged(seed) => self.palette_seed = seed,
+            Message::RandomizePalette => {
+                self.palette_seed = palette::new_seed().to_string();
+                self.generate_palette();
+            }
+            Message::GenerateFromSeed => self.generate_palette(),
+            Message::PaletteModeChanged(mode) => self.layer_mut().palette_mode = mode,
+            Message::CosineChanged(param, channel, value) => {
+                let mut raw = self.layer().cosine.raw();
+                raw[param][channel] = value;
+                self.set_cosine(CosinePalette {
+                    a: raw[0],
+                    b: raw[1],
+                    c: raw[2],
+                    d: raw[3],
+                });
+            }
+            Message::CosineTextChanged(text) => {
+                match text.parse::<CosinePalette>() {
+                    Ok(cosine) => {
+                        self.layer_mut().cosine = cosine;
+                        self.palette_status.clear();
+                    }
+                    Err(e) => self.palette_status = e.to_string(),
+                }
+                self.cosine_text = text;
+            }
+            Message::LayerAdd => {
+                //Shading on top of the existing layers is the most common thing to add
+                self.layers.push(Layer {
+                    algorithm: Algorithm::Lighting,
+                    blend: BlendMode::Multiply,
+                    num_colors: 2,
+                    palette: Preset::Grayscale.palette(),
+                    ..Default::default()
+                });
+                self.select_layer(self.layers.len() - 1);
+            }
+            Message::LayerRemove(index) => {
+                self.layers.remove(index);
+                self.select_layer(self.current_layer.min(self.layers.len() - 1));
+            }
+            Message::LayerMoveUp(index) => {
+                self.layers.swap(index, index - 1);
+                self.select_layer(index - 1);
+            }
+            Message::LayerSelected(index) => self.select_layer(index),
+            Message::AlgorithmChanged(algorithm) => self.layer_mut().algorithm = algorithm,
+            Message::TransferChanged(transfer) => self.layer_mut().transfer = transfer,
+            Message::BlendChanged(blend) => self.layer_mut().blend = blend,
+            Message::OpacityChanged(opacity) => self.layer_mut().opacity = opacity,
+            Message::ParamChanged(param) => self.layer_mut().param = param,
+            Message::ScenePathChanged(path) => self.scene_path = path,
+            Message::SaveScene => {
+                self.scene_status = match self.saved_scene().save(Path::new(&self.scene_path)) {
+                    Ok(_) => format!("Saved {}", self.scene_path),
+                    Err(e) => e.to_string(),
+                }
+            }
+            Message::LoadScene => match SavedScene::load(Path::new(&self.scene_path)) {
+                Ok(scene) => {
+                    self.apply_scene(scene);
+                    self.scene_status = format!("Loaded {}", self.scene_path);
+                }
+                Err(e) => self.scene_status = e.to_string(),
+            },
+            Message::ScreenshotClick => *self.pending_screenshot.lock().unwrap() = true,
+            Message::ExportFormatChanged(value) => self.export_format = value,
+            Message::ToggleHelp => self.show_help = !self.show_help,
+            Message::HistoryRecord(entry) => self.history.push(entry),
+            Message::HistoryBack => {
+                let location = self.history.back();
+                self.go_to_location(location);
+            }
+            Message::HistoryForward => {
+                let location = self.history.forward();
+                self.go_to_location(location);
+            }
+            Message::HistorySelected(index) => {
+                let location = self.history.go_to(index);
+                self.go_to_location(location);
+            }
+            Message::ReTextChanged(text) => self.re_text = text,
+            Message::ImTextChanged(text) => self.im_text = text,
+            Message::MagnificationTextChanged(text) => self.magnification_text = text,
+            Message::GoToCoordinates => {
+                self.location_status = match self.go_to_coordinates() {
+                    Ok(note) => note,
+                    Err(e) => e,
+                }
+            }
+            Message::CopyLocation => return iced_winit::clipboard::write(self.location_text()),
+            Message::BookmarkNameChanged(name) => self.bookmark_name = name,
+            Message::AddBookmark => {
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("Bookmark {}", self.bookmarks.len() + 1),
+                    name => name.to_string(),
+                };
+                self.bookmarks
+                    .push(Bookmark::new(&name, self.saved_scene()));
+                self.bookmark_name.clear();
+                self.save_bookmarks();
+            }
+            Message::RemoveBookmark(index) => {
+                self.bookmarks.remove(index);
+                self.save_bookmarks();
+            }
+            Message::BookmarkSelected(index) => {
+                let scene = self.bookmarks[index].scene.clone();
+                let view = scene.view;
+                self.apply_scene(scene);
+                *self.pending_view.lock().unwrap() = None;
+                *self.pending_flight.lock().unwrap() = Some(view);
+            }
+            Message::BookmarkThumbnail(index, name, thumbnail) => {
+                if let Some(bookmark) = self.bookmarks.get_mut(index) {
+                    if bookmark.name == name {
+                        bookmark.thumbnail = Some(thumbnail);
+                    }
+                }
+            }
         }
         Command::none()
     }
@@ -133,33 +953,163 @@ impl Program for Controls {
             row![open_button].padding(10)
         } else {
             let close_button = button("Close").on_press(Message::ToggleUi);
-            let fractals = vec![
-                Fractals::Mandelbrot,
-                Fractals::BurningShip,
-                Fractals::Tricorn,
-                Fractals::Feather,
-                Fractals::Eye,
-            ];
-            let fractal_list =
-                pick_list(fractals, Some(self.current_fractal), Message::ChangeFractal);
+            let layer = self.layer();
+            let scene_file = column![
+                text_input("Scene file", &self.scene_path, Message::ScenePathChanged),
+                row![
+                    button("Save").on_press(Message::SaveScene),
+                    button("Load").on_press(Message::LoadScene),
+                ]
+                .spacing(5),
+                text(&self.scene_status).size(14),
+            ]
+            .spacing(5);
+            let fractal_list = pick_list(
+                &Fractals::ALL[..],
+                Some(self.current_fractal),
+                Message::ChangeFractal,
+            );
             let num_colors_slider = slider(
                 RangeInclusive::new(1, 1000),
-                self.num_colors,
+                layer.num_colors,
                 Message::NumColorsChanged,
             );
-            let num_iters_slider = slider(
-                RangeInclusive::new(1, 2000),
-                self.num_iters,
-                Message::NumItersChanged,
+            let mut iterations = column![checkbox(
+                "Auto iterations",
+                self.auto_iters,
+                Message::ToggleAutoIters
+            )]
+            .spacing(10);
+            iterations = if self.auto_iters {
+                iterations.push(text(format!("Num iters {}", self.num_iters)))
+            } else {
+                //Goes in powers of ten like the tolerance, so the whole range fits on the slider and
+                //typed in counts don't get cut off when it's touched
+                iterations
+                    .push(
+                        row![
+                            text("Num iters").width(80),
+                            text_input(
+                                "Iterations",
+                                &self.num_iters_text,
+                                Message::NumItersTextChanged
+                            ),
+                        ]
+                        .spacing(5),
+                    )
+                    .push(
+                        slider(
+                            0.0..=(MAX_ITERATIONS as f32).log10(),
+                            (self.num_iters as f32).log10(),
+                            |x| Message::NumItersChanged(10f32.powf(x).round() as u32),
+                        )
+                        .step(0.01),
+                    )
+            };
+            let msaa_slider = slider(
+                RangeInclusive::new(1, MAX_MSAA),
+                self.msaa,
+                Message::MsaaChanged,
             );
-            let msaa_slider = slider(RangeInclusive::new(1, 8), self.msaa, Message::MsaaChanged);
+            let mut antialiasing = column![
+                text("Anti Aliasing"),
+                msaa_slider,
+                row![
+                    pick_list(
+                        &SamplePattern::ALL[..],
+                        Some(self.aa_pattern),
+                        Message::AaPatternChanged
+                    ),
+                    pick_list(
+                        &Filter::ALL[..],
+                        Some(self.aa_filter),
+                        Message::AaFilterChanged
+                    ),
+                ]
+                .spacing(5),
+                checkbox(
+                    "Different pattern per pixel",
+                    self.aa_per_pixel,
+                    Message::ToggleAaPerPixel
+                ),
+                checkbox(
+                    "Adaptive, more samples on edges",
+                    self.adaptive_aa,
+                    Message::ToggleAdaptiveAa
+                ),
+            ]
+            .spacing(10);
+            if self.adaptive_aa {
+                antialiasing = antialiasing
+                    .push(text(format!(
+                        "Edge threshold {:.3}",
+                        self.adaptive_threshold
+                    )))
+                    .push(
+                        slider(0.001..=0.2, self.adaptive_threshold, |x| {
+                            Message::AdaptiveThresholdChanged(x)
+                        })
+                        .step(0.001),
+                    );
+            }
+            let zoom_factor_slider = slider(
+                RangeInclusive::new(1.05, 2.0),
+                self.zoom_factor,
+                Message::ZoomFactorChanged,
+            )
+            .step(0.05);
 
             let num_colors_label = text("Num colors");
-            let num_iters_label = text("Num iters");
-            let msaa_label = text("Anti Aliasing");
+            let mut navigation = column![
+                checkbox("Minimap", self.show_minimap, Message::ToggleMinimap),
+                checkbox(
+                    "Orbit under the cursor",
+                    self.show_orbit,
+                    Message::ToggleOrbit
+                ),
+                checkbox(
+                    "Smooth navigation",
+                    self.smooth_navigation,
+                    Message::ToggleSmoothNavigation
+                )
+            ]
+            .spacing(10);
+            if self.smooth_navigation {
+                navigation = navigation
+                    .push(text(format!("Smoothing {:.2}s", self.smoothing)))
+                    .push(slider(0.01..=0.5, self.smoothing, Message::SmoothingChanged).step(0.01));
+            }
+            let mut period = column![checkbox(
+                "Period checking",
+                self.period_checking,
+                Message::TogglePeriodChecking
+            )]
+            .spacing(10);
+            if self.period_checking {
+                //Goes in powers of ten
+                period = period
+                    .push(text(format!("Tolerance {:.0e}", self.period_tolerance)))
+                    .push(
+                        slider(-8.0..=-2.0, self.period_tolerance.log10(), |x| {
+                            Message::PeriodToleranceChanged(10f32.powf(x))
+                        })
+                        .step(0.25),
+                    );
+            }
+            let rotation = row![
+                text("Rotation").width(80),
+                text_input("Degrees", &self.rotation_text, Message::RotationTextChanged),
+            ]
+            .spacing(5);
+            let zoom_factor_label = text(format!("Zoom factor {:.2}", self.zoom_factor));
 
             let smooth_toggle = checkbox("Smooth?", self.smooth_enabled, Message::ToggleSmooth);
 
+            let palette_mode = pick_list(
+                &[PaletteMode::Stops, PaletteMode::Cosine][..],
+                Some(layer.palette_mode),
+                Message::PaletteModeChanged,
+            );
             let colors_label = row![
                 text("Colors"),
                 button(text("+").horizontal_alignment(alignment::Horizontal::Center))
@@ -168,69 +1118,168 @@ impl Program for Controls {
                     .on_press(Message::ColorAdd)
             ]
             .spacing(5);
-            let colors = scrollable(
-                column(
-                    self.colors
-                        .iter()
-                        .zip(0..self.colors.len())
-                        .map(|t| {
-                            row![
-                                ColorPicker::new(
-                                    self.color_editing_index == t.1 && self.editing_color,
-                                    *t.0,
-                                    button("")
-                                        .on_press(Message::OpenColorPicker(t.1))
-                                        .width(30)
-                                        .height(30)
-                                        .style(iced_winit::theme::Button::Custom(Box::new(
-                                            crate::theme::Theme { color: *t.0 },
-                                        ))),
-                                    Message::CancelColor,
-                                    Message::SubmitColor,
-                                ),
-                                text(color_hex(t.0))
-                                    .style(theme::Text::Color(*t.0))
-                                    .width(80),
-                                button(
-                                    text("X").horizontal_alignment(alignment::Horizontal::Center),
-                                )
+            let presets = pick_list(
+                &Preset::ALL[..],
+                Preset::ALL
+                    .into_iter()
+                    .find(|p| p.to_string() == layer.palette.name),
+                Message::PresetSelected,
+            )
+            .placeholder("Presets");
+            let random = row![
+                text_input("Seed", &self.palette_seed, Message::PaletteSeedChanged)
+                    .on_submit(Message::GenerateFromSeed)
+                    .width(80),
+                button("Randomize").on_press(Message::RandomizePalette),
+            ]
+            .spacing(5);
+            let library = pick_list(
+                self.palette_library.clone(),
+                self.palette_library
+                    .iter()
+                    .find(|e| e.path == Path::new(&self.palette_path))
+                    .cloned(),
+                Message::LibraryPaletteSelected,
+            )
+            .placeholder("Palette library");
+            let palette_file = column![
+                text_input(
+                    "Palette file",
+                    &self.palette_path,
+                    Message::PalettePathChanged
+                ),
+                row![
+                    button("Import").on_press(Message::ImportPalette),
+                    button("Export").on_press(Message::ExportPalette),
+                ]
+                .spacing(5),
+                text(&self.palette_status).size(14),
+            ]
+            .spacing(5);
+            let colors = column(
+                layer
+                    .palette
+                    .colors
+                    .iter()
+                    .zip(0..layer.palette.colors.len())
+                    .map(|t| {
+                        row![
+                            ColorPicker::new(
+                                self.color_editing_index == t.1 && self.editing_color,
+                                *t.0,
+                                button("")
+                                    .on_press(Message::OpenColorPicker(t.1))
+                                    .width(30)
+                                    .height(30)
+                                    .style(iced_winit::theme::Button::Custom(Box::new(
+                                        crate::theme::Theme { color: *t.0 },
+                                    ))),
+                                Message::CancelColor,
+                                Message::SubmitColor,
+                            ),
+                            text(color_hex(t.0))
+                                .style(theme::Text::Color(*t.0))
+                                .width(80),
+                            button(text("X").horizontal_alignment(alignment::Horizontal::Center),)
                                 .on_press(Message::ColorRemove(t.1))
                                 .width(30)
                                 .height(30),
-                            ]
-                            .spacing(20)
-                            .into()
-                        })
-                        .collect(),
-                )
-                .padding(12)
-                .spacing(10),
-            );
+                        ]
+                        .spacing(20)
+                        .into()
+                    })
+                    .collect(),
+            )
+            .padding(12)
+            .spacing(10);
 
-            row![column![
-                close_button,
-                fractal_list,
-                num_iters_label,
-                num_iters_slider,
-                num_colors_label,
-                num_colors_slider,
-                msaa_label,
-                msaa_slider,
-                smooth_toggle,
-                colors_label,
-                colors
-            ]
-            .spacing(10)]
+            row![scrollable(
+                column![
+                    row![
+                        close_button,
+                        button("Screenshot").on_press(Message::ScreenshotClick),
+                        pick_list(
+                            &ExportFormat::ALL[..],
+                            Some(self.export_format),
+                            Message::ExportFormatChanged
+                        ),
+                        button("?").on_press(Message::ToggleHelp),
+                    ]
+                    .spacing(5),
+                    scene_file,
+                    self.location_view(),
+                    self.history_view(),
+                    self.bookmarks_view(),
+                    fractal_list,
+                    iterations,
+                    antialiasing,
+                    checkbox(
+                        "Progressive rendering",
+                        self.progressive,
+                        Message::ToggleProgressive
+                    ),
+                    period,
+                    zoom_factor_label,
+                    zoom_factor_slider,
+                    navigation,
+                    rotation,
+                    smooth_toggle,
+                    self.layers_view(),
+                    num_colors_label,
+                    num_colors_slider,
+                    palette_mode,
+                    random,
+                    match layer.palette_mode {
+                        PaletteMode::Stops =>
+                            column![colors_label, presets, library, palette_file, colors]
+                                .spacing(10),
+                        PaletteMode::Cosine => self.cosine_view(),
+                    }
+                ]
+                .spacing(10)
+            )
+            .height(Length::Fill)]
             .spacing(20)
             .width(220)
             .padding(10)
         };
-        container(column![
-            // button("Screenshot").on_press(Message::ScreenshotClick),
-            content,
-        ])
-        .width(Length::Fill)
-        .align_x(alignment::Horizontal::Right)
-        .into()
+        let depth = match *self.minimap_depth.lock().unwrap() {
+            Some(zoom) if self.show_minimap => Some(zoom),
+            _ => None,
+        };
+        //Help goes on the left, the zoom depth sits at the bottom next to the minimap
+        let mut side = column![].height(Length::Fill);
+        if self.show_help {
+            side = side.push(self.help_view());
+        }
+        if let Some(zoom) = depth {
+            let readout = container(text(format!("{:.3e}x", zoom / BASE_ZOOM)).size(14))
+                .padding(5)
+                .style(theme::Container::Custom(Box::new(crate::theme::Panel)));
+            side = side
+                .push(vertical_space(Length::Fill))
+                .push(container(readout).padding([
+                    0,
+                    0,
+                    minimap::MARGIN as u16,
+                    (minimap::WIDTH + minimap::MARGIN * 2.0) as u16,
+                ]));
+        }
+        //Nothing but the fractal in between, that's where the orbit tooltip shows up
+        let fractal_area = Space::new(Length::Fill, Length::Fill);
+        let fractal_area: Element<'_, Message, iced_wgpu::Renderer> =
+            match &*self.orbit_summary.lock().unwrap() {
+                Some(summary) if self.show_orbit => {
+                    tooltip(fractal_area, summary, tooltip::Position::FollowCursor)
+                        .size(14)
+                        .padding(5)
+                        .style(theme::Container::Custom(Box::new(crate::theme::Panel)))
+                        .into()
+                }
+                _ => fractal_area.into(),
+            };
+        row![side, fractal_area, content]
+            .height(Length::Fill)
+            .into()
     }
 }
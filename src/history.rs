@@ -0,0 +1,177 @@
+use iced_winit::image;
+
+use crate::controls::{Fractals, View};
+
+//Oldest entries get dropped after this many
+pub const MAX_HISTORY: usize = 50;
+pub const THUMBNAIL_WIDTH: u32 = 64;
+
+//Where we are and what we're looking at, without the coloring
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub fractal: Fractals,
+    pub num_iters: u32,
+    pub view: View,
+    //In degrees
+    pub rotation: f32,
+}
+
+impl Location {
+    //Iterations don't count so dragging the slider doesn't flood the history, and the view only
+    //has to be within half a pixel since the smoothing never quite gets there
+    pub fn same_place(&self, other: &Self) -> bool {
+        let distance = ((self.view.position[0] - other.view.position[0]).powi(2)
+            + (self.view.position[1] - other.view.position[1]).powi(2))
+        .sqrt();
+        self.fractal == other.fractal
+            && self.rotation == other.rotation
+            && (self.view.zoom / other.view.zoom - 1.0).abs() < 1e-3
+            && distance * self.view.zoom < 0.5
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub location: Location,
+    pub thumbnail: image::Handle,
+}
+
+#[derive(Default, Clone)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: usize,
+}
+
+impl History {
+    //Going somewhere new after going back throws away everything after the current entry, like
+    //in a browser. Coming back to the current entry (after undoing for example) doesn't count
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if let Some(current) = self.entries.get(self.current) {
+            if current.location.same_place(&entry.location) {
+                return;
+            }
+            self.entries.truncate(self.current + 1);
+        }
+        self.entries.push(entry);
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    pub fn back(&mut self) -> Option<Location> {
+        self.go_to(self.current.checked_sub(1)?)
+    }
+
+    pub fn forward(&mut self) -> Option<Location> {
+        self.go_to(self.current + 1)
+    }
+
+    pub fn go_to(&mut self, index: usize) -> Option<Location> {
+        let entry = self.entries.get(index)?;
+        self.current = index;
+        Some(entry.location)
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Each x is a whole screen away from the one before
+    fn entry(x: f64) -> HistoryEntry {
+        HistoryEntry {
+            location: Location {
+                fractal: Fractals::default(),
+                num_iters: 1000,
+                view: View {
+                    position: [x, 0.0],
+                    zoom: 500.0,
+                },
+                rotation: 0.0,
+            },
+            thumbnail: image::Handle::from_pixels(1, 1, vec![0; 4]),
+        }
+    }
+
+    fn positions(history: &History) -> Vec<f64> {
+        history
+            .entries()
+            .iter()
+            .map(|e| e.location.view.position[0])
+            .collect()
+    }
+
+    #[test]
+    fn back_and_forward() {
+        let mut history = History::default();
+        assert!(history.back().is_none());
+        for x in 0..3 {
+            history.push(entry(x as f64));
+        }
+        assert_eq!(history.back().unwrap().view.position[0], 1.0);
+        assert_eq!(history.back().unwrap().view.position[0], 0.0);
+        assert!(!history.can_go_back());
+        assert!(history.back().is_none());
+        assert_eq!(history.forward().unwrap().view.position[0], 1.0);
+        assert!(history.can_go_forward());
+    }
+
+    #[test]
+    fn going_somewhere_new_drops_what_was_ahead() {
+        let mut history = History::default();
+        for x in 0..4 {
+            history.push(entry(x as f64));
+        }
+        history.back();
+        history.back();
+        history.push(entry(10.0));
+        assert_eq!(positions(&history), vec![0.0, 1.0, 10.0]);
+        assert_eq!(history.current(), 2);
+        assert!(history.forward().is_none());
+    }
+
+    #[test]
+    fn oldest_gets_dropped() {
+        let mut history = History::default();
+        for x in 0..MAX_HISTORY + 5 {
+            history.push(entry(x as f64));
+        }
+        assert_eq!(history.entries().len(), MAX_HISTORY);
+        assert_eq!(positions(&history)[0], 5.0);
+        assert_eq!(history.current(), MAX_HISTORY - 1);
+    }
+
+    #[test]
+    fn same_place_is_not_pushed_twice() {
+        let mut history = History::default();
+        history.push(entry(0.0));
+        //A fraction of a pixel off, with different iterations
+        let mut nearby = entry(0.0005);
+        nearby.location.num_iters = 5000;
+        history.push(nearby);
+        assert_eq!(history.entries().len(), 1);
+        //Coming back to the current entry after going back doesn't cut off the rest either
+        history.push(entry(1.0));
+        history.back();
+        history.push(entry(0.0));
+        assert_eq!(positions(&history), vec![0.0, 1.0]);
+        assert_eq!(history.current(), 0);
+    }
+}
@@ -0,0 +1,239 @@
+use iced_wgpu::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer};
+
+//Two of these hold everything the coloring needs to know about a pixel's orbit
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+
+//Orbit data for one sample of every pixel, written by the compute shader and read by the
+//coloring pass, so recoloring doesn't have to iterate again. It wraps around, so panning just
+//moves where the screen starts in it instead of moving everything over
+pub struct IterationCache {
+    size: [u32; 2],
+    //Where the top left pixel of the screen is kept
+    origin: [u32; 2],
+    //What the last pan moved it by, if the pixels that stayed on the screen are being kept
+    shift: Option<[i32; 2]>,
+    //The origin, the shift and the first tile being iterated, for the shaders
+    uniform_buffer: Buffer,
+    //How many pixels got iterated and how many of those ran out of iterations, counted by the
+    //compute shader and copied into the readback buffer to get them out
+    stats_buffer: Buffer,
+    stats_readback: Buffer,
+    pub write_layout: BindGroupLayout,
+    pub read_layout: BindGroupLayout,
+    pub write_bind_group: BindGroup,
+    pub read_bind_group: BindGroup,
+}
+
+impl IterationCache {
+    pub fn new(device: &wgpu::Device, size: [u32; 2]) -> Self {
+        let write_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let read_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Uint,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let uniform_entry = |visibility| wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let write_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Iteration cache write"),
+            entries: &[
+                write_entry(0),
+                write_entry(1),
+                uniform_entry(wgpu::ShaderStages::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let read_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Iteration cache read"),
+            entries: &[
+                read_entry(0),
+                read_entry(1),
+                uniform_entry(wgpu::ShaderStages::FRAGMENT),
+            ],
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Iteration cache"),
+            contents: bytemuck::cast_slice(&[0u32; 8]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let stats_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Iteration stats"),
+            contents: bytemuck::cast_slice(&[0u32; 2]),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let stats_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Iteration stats readback"),
+            size: 8,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let buffers = [&uniform_buffer, &stats_buffer];
+        let (write_bind_group, read_bind_group) =
+            create_bind_groups(device, &write_layout, &read_layout, buffers, size);
+        Self {
+            size,
+            origin: [0, 0],
+            shift: None,
+            uniform_buffer,
+            stats_buffer,
+            stats_readback,
+            write_layout,
+            read_layout,
+            write_bind_group,
+            read_bind_group,
+        }
+    }
+
+    //Throws away whatever was in it
+    pub fn resize(&mut self, device: &wgpu::Device, size: [u32; 2]) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.origin = [0, 0];
+        self.shift = None;
+        (self.write_bind_group, self.read_bind_group) = create_bind_groups(
+            device,
+            &self.write_layout,
+            &self.read_layout,
+            [&self.uniform_buffer, &self.stats_buffer],
+            size,
+        );
+    }
+
+    //Starts iterating again. With a shift the pixels that are still on the screen get kept, the
+    //new pixel at p was at p + shift before, without one everything gets iterated
+    pub fn shift(&mut self, queue: &wgpu::Queue, shift: Option<[i32; 2]>) {
+        queue.write_buffer(&self.stats_buffer, 0, bytemuck::cast_slice(&[0u32; 2]));
+        if let Some(shift) = shift {
+            self.origin = [0, 1].map(|i| {
+                (self.origin[i] as i64 + shift[i] as i64).rem_euclid(self.size[i].max(1) as i64)
+                    as u32
+            });
+        }
+        self.shift = shift;
+    }
+
+    //Has to happen before every batch of tiles, so it has to be in its own submission
+    pub fn write_uniforms(&self, queue: &wgpu::Queue, first_tile: u32) {
+        let [x, y] = self.shift.unwrap_or([0, 0]);
+        let data = [
+            self.origin[0],
+            self.origin[1],
+            x as u32,
+            y as u32,
+            self.shift.is_some() as u32,
+            first_tile,
+            0,
+            0,
+        ];
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&data));
+    }
+
+    //How many pixels got iterated since the last shift, and how many of them ran out of
+    //iterations without escaping or finding a cycle. Waits for the GPU
+    pub fn read_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> [u32; 2] {
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.stats_buffer, 0, &self.stats_readback, 0, 8);
+        queue.submit(Some(encoder.finish()));
+        let slice = self.stats_readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let data = slice.get_mapped_range();
+        let stats: &[u32] = bytemuck::cast_slice(&data);
+        let stats = [stats[0], stats[1]];
+        drop(data);
+        self.stats_readback.unmap();
+        stats
+    }
+}
+
+fn create_bind_groups(
+    device: &wgpu::Device,
+    write_layout: &BindGroupLayout,
+    read_layout: &BindGroupLayout,
+    [uniform_buffer, stats_buffer]: [&Buffer; 2],
+    size: [u32; 2],
+) -> (BindGroup, BindGroup) {
+    let views = ["Iteration cache A", "Iteration cache B"].map(|label| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size[0].max(1),
+                    height: size[1].max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    });
+    let entries = [
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&views[0]),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&views[1]),
+        },
+        wgpu::BindGroupEntry {
+            binding: 2,
+            resource: uniform_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 3,
+            resource: stats_buffer.as_entire_binding(),
+        },
+    ];
+    //Only the compute shader counts
+    let write_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: write_layout,
+        entries: &entries,
+    });
+    let read_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: read_layout,
+        entries: &entries[..3],
+    });
+    (write_bind_group, read_bind_group)
+}
@@ -0,0 +1,130 @@
+use crate::controls::{View, BASE_ZOOM};
+
+//What auto iterations starts from at 1x, and how many it adds every time the magnification
+//doubles, deeper views need longer orbits to tell points apart
+const BASE_ITERATIONS: f32 = 200.0;
+const PER_DOUBLING: f32 = 100.0;
+
+//Most iterations that can be asked for, by auto iterations or typed in
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+const MIN_ITERATIONS: u32 = 50;
+
+//With more than this fraction of the pixels running out of iterations there's detail missing,
+//with less than the other one there's iterations to spare
+const RAISE_ABOVE: f32 = 0.01;
+const LOWER_BELOW: f32 = 0.001;
+//How much the count changes at a time, and how far it can get from what the zoom says
+const STEP: f32 = 1.5;
+const MAX_FACTOR: f32 = 64.0;
+const MIN_FACTOR: f32 = 0.25;
+
+//Picks the iteration count from the zoom, then nudges it up or down depending on how many
+//pixels ran out of iterations last time
+pub struct AutoIterations {
+    factor: f32,
+    //Where it last went up, it doesn't come back down there so it can't go back and forth
+    raised_at: Option<View>,
+}
+
+impl Default for AutoIterations {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            raised_at: None,
+        }
+    }
+}
+
+impl AutoIterations {
+    pub fn iterations(&self, zoom: f64) -> u32 {
+        let doublings = (zoom / BASE_ZOOM).log2().max(0.0) as f32;
+        let iterations = (BASE_ITERATIONS + PER_DOUBLING * doublings) * self.factor;
+        (iterations.round() as u32).clamp(MIN_ITERATIONS, MAX_ITERATIONS)
+    }
+
+    //Takes how many pixels got iterated and how many of them ran out of iterations
+    pub fn update(&mut self, view: View, [pixels, limited]: [u32; 2]) {
+        if pixels == 0 {
+            return;
+        }
+        let fraction = limited as f32 / pixels as f32;
+        if fraction > RAISE_ABOVE {
+            self.factor = (self.factor * STEP).min(MAX_FACTOR);
+            self.raised_at = Some(view);
+        } else if fraction < LOWER_BELOW && self.raised_at != Some(view) {
+            self.factor = (self.factor / STEP).max(MIN_FACTOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(zoom: f64) -> View {
+        View {
+            position: [0.0, 0.0],
+            zoom,
+        }
+    }
+
+    #[test]
+    fn follows_the_zoom() {
+        let auto = AutoIterations::default();
+        assert_eq!(auto.iterations(BASE_ZOOM), BASE_ITERATIONS as u32);
+        assert_eq!(auto.iterations(BASE_ZOOM / 8.0), BASE_ITERATIONS as u32);
+        assert_eq!(
+            auto.iterations(BASE_ZOOM * 1024.0),
+            (BASE_ITERATIONS + 10.0 * PER_DOUBLING) as u32
+        );
+        assert_eq!(auto.iterations(f64::INFINITY), MAX_ITERATIONS);
+    }
+
+    #[test]
+    fn raises_and_lowers() {
+        let mut auto = AutoIterations::default();
+        let start = auto.iterations(BASE_ZOOM);
+        auto.update(view(BASE_ZOOM), [1000, 100]);
+        assert!(auto.iterations(BASE_ZOOM) > start);
+        //Somewhere else with nothing running out it comes back down
+        auto.update(view(BASE_ZOOM * 2.0), [1000, 0]);
+        assert_eq!(auto.iterations(BASE_ZOOM), start);
+        //In between it stays put
+        auto.update(view(BASE_ZOOM * 2.0), [1000, 5]);
+        assert_eq!(auto.iterations(BASE_ZOOM), start);
+    }
+
+    #[test]
+    fn doesnt_go_back_down_where_it_went_up() {
+        let mut auto = AutoIterations::default();
+        auto.update(view(BASE_ZOOM), [1000, 100]);
+        let raised = auto.iterations(BASE_ZOOM);
+        auto.update(view(BASE_ZOOM), [1000, 0]);
+        assert_eq!(auto.iterations(BASE_ZOOM), raised);
+    }
+
+    #[test]
+    fn stays_in_range() {
+        let mut auto = AutoIterations::default();
+        for _ in 0..100 {
+            auto.update(view(BASE_ZOOM), [1000, 1000]);
+        }
+        assert_eq!(
+            auto.iterations(BASE_ZOOM),
+            (BASE_ITERATIONS * MAX_FACTOR) as u32
+        );
+        for i in 0..100 {
+            auto.update(view(i as f64), [1000, 0]);
+        }
+        assert_eq!(
+            auto.iterations(BASE_ZOOM),
+            (BASE_ITERATIONS * MIN_FACTOR) as u32
+        );
+        //Nothing iterated says nothing
+        auto.update(view(BASE_ZOOM), [0, 0]);
+        assert_eq!(
+            auto.iterations(BASE_ZOOM),
+            (BASE_ITERATIONS * MIN_FACTOR) as u32
+        );
+    }
+}
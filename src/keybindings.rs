@@ -0,0 +1,451 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use iced_winit::keyboard::{KeyCode, Modifiers};
+
+pub const KEYBINDINGS_FILE: &str = "keybindings.cfg";
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub enum Action {
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    ZoomIn,
+    ZoomOut,
+    RotateLeft,
+    RotateRight,
+    ResetView,
+    Back,
+    Forward,
+    ToggleUi,
+    CycleFractal,
+    MoreIters,
+    FewerIters,
+    ToggleOrbit,
+    Screenshot,
+    Fullscreen,
+    ToggleHelp,
+}
+
+impl Action {
+    pub const ALL: [Self; 19] = [
+        Self::PanLeft,
+        Self::PanRight,
+        Self::PanUp,
+        Self::PanDown,
+        Self::ZoomIn,
+        Self::ZoomOut,
+        Self::RotateLeft,
+        Self::RotateRight,
+        Self::ResetView,
+        Self::Back,
+        Self::Forward,
+        Self::ToggleUi,
+        Self::CycleFractal,
+        Self::MoreIters,
+        Self::FewerIters,
+        Self::ToggleOrbit,
+        Self::Screenshot,
+        Self::Fullscreen,
+        Self::ToggleHelp,
+    ];
+
+    //Name used in the config file
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::PanLeft => "pan_left",
+            Self::PanRight => "pan_right",
+            Self::PanUp => "pan_up",
+            Self::PanDown => "pan_down",
+            Self::ZoomIn => "zoom_in",
+            Self::ZoomOut => "zoom_out",
+            Self::RotateLeft => "rotate_left",
+            Self::RotateRight => "rotate_right",
+            Self::ResetView => "reset_view",
+            Self::Back => "back",
+            Self::Forward => "forward",
+            Self::ToggleUi => "toggle_ui",
+            Self::CycleFractal => "cycle_fractal",
+            Self::MoreIters => "more_iters",
+            Self::FewerIters => "fewer_iters",
+            Self::ToggleOrbit => "toggle_orbit",
+            Self::Screenshot => "screenshot",
+            Self::Fullscreen => "fullscreen",
+            Self::ToggleHelp => "toggle_help",
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PanLeft => write!(f, "Pan left"),
+            Self::PanRight => write!(f, "Pan right"),
+            Self::PanUp => write!(f, "Pan up"),
+            Self::PanDown => write!(f, "Pan down"),
+            Self::ZoomIn => write!(f, "Zoom in"),
+            Self::ZoomOut => write!(f, "Zoom out"),
+            Self::RotateLeft => write!(f, "Rotate left"),
+            Self::RotateRight => write!(f, "Rotate right"),
+            Self::ResetView => write!(f, "Reset view"),
+            Self::Back => write!(f, "Back"),
+            Self::Forward => write!(f, "Forward"),
+            Self::ToggleUi => write!(f, "Toggle UI"),
+            Self::CycleFractal => write!(f, "Next fractal"),
+            Self::MoreIters => write!(f, "More iterations"),
+            Self::FewerIters => write!(f, "Fewer iterations"),
+            Self::ToggleOrbit => write!(f, "Show orbits"),
+            Self::Screenshot => write!(f, "Screenshot"),
+            Self::Fullscreen => write!(f, "Fullscreen"),
+            Self::ToggleHelp => write!(f, "Show this help"),
+        }
+    }
+}
+
+//Keys that can be bound, they're written in the config file with their Debug names
+const KEYS: [KeyCode; 93] = {
+    use KeyCode::*;
+    [
+        Key1,
+        Key2,
+        Key3,
+        Key4,
+        Key5,
+        Key6,
+        Key7,
+        Key8,
+        Key9,
+        Key0,
+        A,
+        B,
+        C,
+        D,
+        E,
+        F,
+        G,
+        H,
+        I,
+        J,
+        K,
+        L,
+        M,
+        N,
+        O,
+        P,
+        Q,
+        R,
+        S,
+        T,
+        U,
+        V,
+        W,
+        X,
+        Y,
+        Z,
+        Escape,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+        Snapshot,
+        Pause,
+        Insert,
+        Home,
+        Delete,
+        End,
+        PageDown,
+        PageUp,
+        Left,
+        Up,
+        Right,
+        Down,
+        Backspace,
+        Enter,
+        Space,
+        Numpad0,
+        Numpad1,
+        Numpad2,
+        Numpad3,
+        Numpad4,
+        Numpad5,
+        Numpad6,
+        Numpad7,
+        Numpad8,
+        Numpad9,
+        NumpadAdd,
+        NumpadDivide,
+        NumpadDecimal,
+        NumpadEnter,
+        NumpadMultiply,
+        NumpadSubtract,
+        Apostrophe,
+        Backslash,
+        Comma,
+        Equals,
+        Grave,
+        LBracket,
+        Minus,
+        Period,
+        Plus,
+        RBracket,
+        Semicolon,
+        Slash,
+        Tab,
+    ]
+};
+
+const MODIFIERS: [(&str, Modifiers); 4] = [
+    ("Ctrl", Modifiers::CTRL),
+    ("Shift", Modifiers::SHIFT),
+    ("Alt", Modifiers::ALT),
+    ("Logo", Modifiers::LOGO),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy)]
+pub struct Binding {
+    pub key: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Binding {
+    const fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            modifiers: Modifiers::empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, modifier) in MODIFIERS {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+impl std::str::FromStr for Binding {
+    type Err = String;
+
+    //Something like "Ctrl+Shift+S"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let mut modifiers = Modifiers::empty();
+        for part in parts {
+            let (_, modifier) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("unknown modifier \"{part}\""))?;
+            modifiers |= *modifier;
+        }
+        let key = KEYS
+            .into_iter()
+            .find(|k| format!("{k:?}").eq_ignore_ascii_case(key))
+            .ok_or_else(|| format!("unknown key \"{key}\""))?;
+        Ok(Self { key, modifiers })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyBindings {
+    pub bindings: Vec<(Action, Vec<Binding>)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use KeyCode::*;
+        let bindings = |keys: &[KeyCode]| keys.iter().copied().map(Binding::new).collect();
+        let alt = |key| Binding {
+            key,
+            modifiers: Modifiers::ALT,
+        };
+        Self {
+            bindings: vec![
+                (Action::PanLeft, bindings(&[Left, A])),
+                (Action::PanRight, bindings(&[Right, D])),
+                (Action::PanUp, bindings(&[Up, W])),
+                (Action::PanDown, bindings(&[Down, S])),
+                (Action::ZoomIn, bindings(&[Plus, Equals, NumpadAdd])),
+                (Action::ZoomOut, bindings(&[Minus, NumpadSubtract])),
+                (Action::RotateLeft, bindings(&[Q])),
+                (Action::RotateRight, bindings(&[E])),
+                (Action::ResetView, bindings(&[R, Home])),
+                (Action::Back, vec![alt(Left), Binding::new(Backspace)]),
+                (Action::Forward, vec![alt(Right)]),
+                (Action::ToggleUi, bindings(&[U])),
+                (Action::CycleFractal, bindings(&[F])),
+                (Action::MoreIters, bindings(&[RBracket])),
+                (Action::FewerIters, bindings(&[LBracket])),
+                (Action::ToggleOrbit, bindings(&[O])),
+                (Action::Screenshot, bindings(&[P, F12])),
+                (Action::Fullscreen, bindings(&[F11])),
+                (Action::ToggleHelp, bindings(&[H, F1])),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    //Writes out the defaults if there's no config file yet so there's something to edit, and
+    //prints what's wrong with it if it can't be read or parsed instead of refusing to start. A
+    //file that's there never gets written over
+    pub fn load_or_default(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents).unwrap_or_else(|e| {
+                eprintln!("Ignoring {}: {e}", path.display());
+                Self::default()
+            }),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                eprintln!("Ignoring {}: {e}", path.display());
+                Self::default()
+            }
+            Err(_) => {
+                let keybindings = Self::default();
+                _ = fs::write(path, keybindings.serialize());
+                keybindings
+            }
+        }
+    }
+
+    //Actions that aren't in the file keep their default keys
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut keybindings = Self::default();
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            let (name, keys) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("bad line \"{line}\""))?;
+            let action = Action::ALL
+                .into_iter()
+                .find(|a| a.key() == name)
+                .ok_or_else(|| format!("unknown action \"{name}\""))?;
+            let keys = keys
+                .split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<Binding>, _>>()?;
+            if let Some((_, bindings)) = keybindings.bindings.iter_mut().find(|(a, _)| *a == action)
+            {
+                *bindings = keys;
+            }
+        }
+        Ok(keybindings)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::from("# action = key, Ctrl+key, ...\n");
+        for (action, bindings) in &self.bindings {
+            _ = writeln!(out, "{} = {}", action.key(), Self::describe(bindings));
+        }
+        out
+    }
+
+    pub fn describe(bindings: &[Binding]) -> String {
+        bindings
+            .iter()
+            .map(Binding::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    //Picks the binding with the most modifiers that are all held down, so Ctrl+S can be bound
+    //separately from S, but Shift+Equals still counts as Equals on keyboards where that's "+"
+    pub fn action(&self, key: KeyCode, modifiers: Modifiers) -> Option<Action> {
+        self.bindings
+            .iter()
+            .flat_map(|(action, bindings)| bindings.iter().map(move |b| (*action, b)))
+            .filter(|(_, b)| b.key == key && modifiers.contains(b.modifiers))
+            .max_by_key(|(_, b)| b.modifiers.bits().count_ones())
+            .map(|(action, _)| action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut keybindings = KeyBindings::default();
+        keybindings.bindings[0].1.push(Binding {
+            key: KeyCode::S,
+            modifiers: Modifiers::CTRL | Modifiers::SHIFT,
+        });
+        keybindings.bindings[1].1.clear();
+        assert_eq!(
+            KeyBindings::parse(&keybindings.serialize()).unwrap(),
+            keybindings
+        );
+    }
+
+    #[test]
+    fn binding_names() {
+        let binding: Binding = "ctrl + shift+s".parse().unwrap();
+        assert_eq!(binding.key, KeyCode::S);
+        assert_eq!(binding.modifiers, Modifiers::CTRL | Modifiers::SHIFT);
+        assert_eq!(binding.to_string(), "Ctrl+Shift+S");
+    }
+
+    #[test]
+    fn missing_actions_keep_defaults() {
+        let keybindings = KeyBindings::parse("# nothing\n\nscreenshot = F5\n").unwrap();
+        let defaults = KeyBindings::default();
+        for ((action, bindings), (_, default)) in
+            keybindings.bindings.iter().zip(&defaults.bindings)
+        {
+            if *action == Action::Screenshot {
+                assert_eq!(*bindings, vec![Binding::new(KeyCode::F5)]);
+            } else {
+                assert_eq!(bindings, default);
+            }
+        }
+    }
+
+    #[test]
+    fn unreadable_file_is_left_alone() {
+        let dir = std::env::temp_dir().join(format!("fractal_viewer_keys_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keybindings.cfg");
+        fs::write(&path, [0xff, 0xfe, 0x00]).unwrap();
+        assert_eq!(KeyBindings::load_or_default(&path), KeyBindings::default());
+        assert_eq!(fs::read(&path).unwrap(), [0xff, 0xfe, 0x00]);
+        //Only a missing one gets the defaults written out
+        fs::remove_file(&path).unwrap();
+        KeyBindings::load_or_default(&path);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            KeyBindings::default().serialize()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed() {
+        for contents in [
+            "screenshot F5",
+            "teleport = T",
+            "screenshot = Hyper+F5",
+            "screenshot = Ctrl+",
+            "screenshot = NotAKey",
+        ] {
+            assert!(KeyBindings::parse(contents).is_err(), "{contents}");
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use iced_winit::{winit::dpi::PhysicalSize, Color};
+
+use crate::{controls::View, overlay::Overlay};
+
+//Size of the inset and its distance from the corner, in logical pixels
+pub const WIDTH: f32 = 200.0;
+pub const HEIGHT: f32 = 150.0;
+pub const MARGIN: f32 = 10.0;
+//How much of the plane fits across the inset
+const EXTENT: f32 = 4.0;
+//Once the view would be smaller than this many pixels it's drawn as a marker instead
+const MIN_RECTANGLE: f32 = 6.0;
+//Iterations are capped so the inset stays cheap, it's too small to show much detail anyway
+pub const MAX_ITERS: u32 = 300;
+
+//The whole fractal in the bottom left corner, in physical pixels
+pub struct Minimap {
+    pub origin: [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl Minimap {
+    pub fn new(window_size: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        let scale = scale_factor as f32;
+        let size = [WIDTH * scale, HEIGHT * scale];
+        Self {
+            origin: [
+                MARGIN * scale,
+                window_size.height as f32 - (HEIGHT + MARGIN) * scale,
+            ],
+            size,
+        }
+    }
+
+    //What the inset is looking at
+    pub fn view(&self) -> View {
+        View {
+            position: [0.0, 0.0],
+            zoom: f64::from(self.size[0] / EXTENT),
+        }
+    }
+
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        (0..2).all(|i| point[i] >= self.origin[i] && point[i] <= self.origin[i] + self.size[i])
+    }
+
+    //Point on the plane under a pixel of the window
+    pub fn to_plane(&self, point: [f32; 2]) -> [f32; 2] {
+        let zoom = self.view().zoom as f32;
+        [0, 1].map(|i| (point[i] - self.origin[i] - self.size[i] * 0.5) / zoom)
+    }
+
+    //Pixel of the window showing a point on the plane, kept inside the inset
+    pub fn to_screen(&self, point: [f32; 2]) -> [f32; 2] {
+        let zoom = self.view().zoom as f32;
+        [0, 1].map(|i| {
+            (point[i] * zoom + self.origin[i] + self.size[i] * 0.5)
+                .clamp(self.origin[i], self.origin[i] + self.size[i])
+        })
+    }
+
+    //Frames the inset and outlines the view, the corners have to go around in order. Returns
+    //whether the view was too small and got a marker instead
+    pub fn draw(&self, overlay: &mut Overlay, center: [f32; 2], corners: [[f32; 2]; 4]) -> bool {
+        let end = [self.origin[0] + self.size[0], self.origin[1] + self.size[1]];
+        overlay.rectangle(self.origin, end, Color::from_rgb(0.5, 0.5, 0.5));
+
+        let corners = corners.map(|c| self.to_screen(c));
+        let diagonal = (corners[0][0] - corners[2][0]).hypot(corners[0][1] - corners[2][1]);
+        if diagonal < MIN_RECTANGLE {
+            let [x, y] = self.to_screen(center);
+            let radius = MIN_RECTANGLE * 1.5;
+            overlay.line([x - radius, y], [x + radius, y], Color::WHITE);
+            overlay.line([x, y - radius], [x, y + radius], Color::WHITE);
+            return true;
+        }
+        for i in 0..4 {
+            overlay.line(corners[i], corners[(i + 1) % 4], Color::WHITE);
+        }
+        false
+    }
+}
@@ -0,0 +1,109 @@
+use crate::controls::Fractals;
+
+//Longest cycle that gets looked for at the end of an orbit that didn't escape
+const MAX_PERIOD: usize = 256;
+//How close two points have to be to count as the same point of a cycle, relative to |z|
+const PERIOD_TOLERANCE: f32 = 1e-4;
+
+fn square(z: [f32; 2]) -> [f32; 2] {
+    [z[0] * z[0] - z[1] * z[1], 2.0 * z[0] * z[1]]
+}
+fn cube(z: [f32; 2]) -> [f32; 2] {
+    let x2 = z[0] * z[0];
+    let y2 = z[1] * z[1];
+    [z[0] * x2 - 3.0 * z[0] * y2, 3.0 * x2 * z[1] - z[1] * y2]
+}
+fn div(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    let denominator = 1.0 / (b[0] * b[0] + b[1] * b[1]);
+    [
+        (a[0] * b[0] + a[1] * b[1]) * denominator,
+        (a[1] * b[0] - a[0] * b[1]) * denominator,
+    ]
+}
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+fn length(z: [f32; 2]) -> f32 {
+    z[0].hypot(z[1])
+}
+
+//Same formulas as the shader, minus the shortcuts that skip the inside of the set
+fn iterate(fractal: Fractals, z: [f32; 2], c: [f32; 2]) -> [f32; 2] {
+    match fractal {
+        Fractals::Mandelbrot => add(square(z), c),
+        Fractals::BurningShip => add(square([z[0].abs(), z[1].abs()]), c),
+        Fractals::Tricorn => add(square([z[0], -z[1]]), c),
+        Fractals::Feather => add(div(cube(z), [1.0 + z[0] * z[0], z[1] * z[1]]), c),
+        Fractals::Eye => add(square(div(z, c)), c),
+    }
+}
+
+//Squared |z| where the shader gives up on a point
+fn bailout(fractal: Fractals) -> f32 {
+    match fractal {
+        Fractals::Feather | Fractals::Eye => 200000.0,
+        _ => 5.0,
+    }
+}
+
+//Where a point goes, z after every iteration starting from 0
+pub struct Orbit {
+    pub points: Vec<[f32; 2]>,
+    pub escaped: bool,
+    //Length of the cycle it settled into, if it didn't escape and found one
+    pub period: Option<usize>,
+}
+
+impl Orbit {
+    pub fn new(fractal: Fractals, c: [f32; 2], max_iter: u32) -> Self {
+        let bailout = bailout(fractal);
+        let mut z = [0.0, 0.0];
+        let mut points = vec![z];
+        while z[0] * z[0] + z[1] * z[1] <= bailout && points.len() <= max_iter as usize {
+            z = iterate(fractal, z, c);
+            points.push(z);
+        }
+        let escaped = z[0] * z[0] + z[1] * z[1] > bailout;
+        let period = if escaped {
+            None
+        } else {
+            //Smallest step back that lands on the same point again
+            let tolerance = PERIOD_TOLERANCE * length(z).max(1.0);
+            points
+                .iter()
+                .rev()
+                .skip(1)
+                .take(MAX_PERIOD)
+                .position(|p| length([p[0] - z[0], p[1] - z[1]]) < tolerance)
+                .map(|i| i + 1)
+        };
+        Self {
+            points,
+            escaped,
+            period,
+        }
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    pub fn final_magnitude(&self) -> f32 {
+        self.points.last().copied().map(length).unwrap_or(0.0)
+    }
+}
+
+impl std::fmt::Display for Orbit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.escaped {
+            writeln!(f, "Escaped after {} iterations", self.iterations())?;
+        } else {
+            writeln!(f, "Still bounded after {} iterations", self.iterations())?;
+            match self.period {
+                Some(period) => writeln!(f, "Period {period}")?,
+                None => writeln!(f, "No period found")?,
+            }
+        }
+        write!(f, "Final |z| {:.6}", self.final_magnitude())
+    }
+}
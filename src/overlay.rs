@@ -0,0 +1,134 @@
+use bytemuck::{Pod, Zeroable};
+use iced_wgpu::{
+    wgpu::{self, Buffer, RenderPipeline},
+    Color,
+};
+
+use crate::scene;
+
+//Enough for a few thousand line segments
+const MAX_VERTICES: usize = 8192;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Default)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+//Lines drawn on top of the fractal but under the UI, in window pixel coordinates
+pub struct Overlay {
+    pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    vertices: Vec<Vertex>,
+    vertex_count: u32,
+}
+
+impl Overlay {
+    pub fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat) -> Self {
+        let module = scene::output_module(
+            device,
+            "overlay.wgsl",
+            texture_format,
+            include_str!("shader/overlay.wgsl"),
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Overlay vertices"),
+            size: (MAX_VERTICES * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            vertex_buffer,
+            vertices: Vec::new(),
+            vertex_count: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, from: [f32; 2], to: [f32; 2], color: Color) {
+        let color = color.into_linear();
+        self.vertices.push(Vertex {
+            position: from,
+            color,
+        });
+        self.vertices.push(Vertex {
+            position: to,
+            color,
+        });
+    }
+
+    pub fn rectangle(&mut self, a: [f32; 2], b: [f32; 2], color: Color) {
+        self.line(a, [b[0], a[1]], color);
+        self.line([b[0], a[1]], b, color);
+        self.line(b, [a[0], b[1]], color);
+        self.line([a[0], b[1]], a, color);
+    }
+
+    //Converts the lines to clip space and sends them to the GPU, call this before drawing
+    pub fn upload(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        let vertices: Vec<Vertex> = self
+            .vertices
+            .iter()
+            .take(MAX_VERTICES)
+            .map(|v| Vertex {
+                position: [
+                    (v.position[0] / width as f32).mul_add(2.0, -1.0),
+                    (v.position[1] / height as f32).mul_add(-2.0, 1.0),
+                ],
+                color: v.color,
+            })
+            .collect();
+        if !vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        }
+        self.vertex_count = vertices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
@@ -0,0 +1,974 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use iced_wgpu::Color;
+
+use crate::scene::MAX_COLORS;
+
+//Where the palette library lives, relative to the working directory
+pub const PALETTE_DIR: &str = "palettes";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Color>,
+    //Positions of the colors inside one repetition of the palette, in the [0, 1) range
+    pub positions: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteFormat {
+    //The viewer's own format, the only one that keeps everything as is
+    Native,
+    //Fractint
+    Map,
+    //GIMP gradient
+    Ggr,
+    //Ultra Fractal gradient
+    Ugr,
+    //Plain list of hex colors, also understands css gradients
+    Hex,
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(std::io::Error),
+    UnknownFormat,
+    Parse(String),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::UnknownFormat => write!(f, "Unknown palette format"),
+            Self::Parse(e) => write!(f, "Invalid palette: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for PaletteError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    TransFlag,
+    UltraFractal,
+    Fire,
+    Ocean,
+    Grayscale,
+    Rainbow,
+    Electric,
+}
+
+impl std::fmt::Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TransFlag => write!(f, "Default"),
+            Self::UltraFractal => write!(f, "Ultra Fractal"),
+            Self::Fire => write!(f, "Fire"),
+            Self::Ocean => write!(f, "Ocean"),
+            Self::Grayscale => write!(f, "Grayscale"),
+            Self::Rainbow => write!(f, "Rainbow"),
+            Self::Electric => write!(f, "Electric"),
+        }
+    }
+}
+
+//a + b * cos(2π(c * t + d)), see https://iquilezles.org/articles/palettes
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct CosinePalette {
+    pub a: [f32; 3],
+    pub b: [f32; 3],
+    pub c: [f32; 3],
+    pub d: [f32; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PaletteMode {
+    #[default]
+    Stops = 0,
+    Cosine = 1,
+}
+
+impl std::fmt::Display for PaletteMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stops => write!(f, "Color stops"),
+            Self::Cosine => write!(f, "Cosine gradient"),
+        }
+    }
+}
+
+//An entry of the palette library, shown in a pick list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LibraryEntry {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl std::fmt::Display for LibraryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Preset {
+    pub const ALL: [Self; 7] = [
+        Self::TransFlag,
+        Self::UltraFractal,
+        Self::Fire,
+        Self::Ocean,
+        Self::Grayscale,
+        Self::Rainbow,
+        Self::Electric,
+    ];
+
+    pub fn palette(self) -> Palette {
+        let name = self.to_string();
+        let rgb = |r, g, b| Color::from_rgb8(r, g, b);
+        match self {
+            //The trans flag colors uwu 🏳️‍⚧️
+            Self::TransFlag => Palette::new(
+                &name,
+                vec![
+                    rgb(85, 205, 252),
+                    rgb(247, 168, 184),
+                    rgb(255, 255, 255),
+                    rgb(247, 168, 184),
+                    rgb(85, 205, 252),
+                ],
+            ),
+            //The gradient Ultra Fractal ships with
+            Self::UltraFractal => Palette {
+                name,
+                colors: vec![
+                    rgb(0, 7, 100),
+                    rgb(32, 107, 203),
+                    rgb(237, 255, 255),
+                    rgb(255, 170, 0),
+                    rgb(0, 2, 0),
+                ],
+                positions: vec![0.0, 0.16, 0.42, 0.6425, 0.8575],
+            },
+            Self::Fire => Palette {
+                name,
+                colors: vec![
+                    rgb(0, 0, 0),
+                    rgb(128, 0, 0),
+                    rgb(255, 64, 0),
+                    rgb(255, 192, 0),
+                    rgb(255, 255, 192),
+                    rgb(255, 128, 0),
+                ],
+                positions: vec![0.0, 0.2, 0.4, 0.6, 0.75, 0.9],
+            },
+            Self::Ocean => Palette::new(
+                &name,
+                vec![
+                    rgb(0, 8, 32),
+                    rgb(0, 48, 110),
+                    rgb(0, 128, 170),
+                    rgb(120, 220, 230),
+                    rgb(0, 90, 140),
+                ],
+            ),
+            Self::Grayscale => Palette::new(&name, vec![rgb(0, 0, 0), rgb(255, 255, 255)]),
+            Self::Rainbow => Palette::new(
+                &name,
+                vec![
+                    rgb(255, 0, 0),
+                    rgb(255, 255, 0),
+                    rgb(0, 255, 0),
+                    rgb(0, 255, 255),
+                    rgb(0, 0, 255),
+                    rgb(255, 0, 255),
+                ],
+            ),
+            Self::Electric => Palette::new(
+                &name,
+                vec![
+                    rgb(10, 0, 40),
+                    rgb(90, 0, 200),
+                    rgb(0, 200, 255),
+                    rgb(255, 255, 255),
+                    rgb(0, 200, 255),
+                    rgb(90, 0, 200),
+                ],
+            ),
+        }
+    }
+}
+
+//The 12 numbers people share and scene files keep, written out in full so they read back exactly
+impl std::fmt::Display for CosinePalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let numbers: Vec<String> = [self.a, self.b, self.c, self.d]
+            .iter()
+            .flatten()
+            .map(|n| n.to_string())
+            .collect();
+        write!(f, "{}", numbers.join(" "))
+    }
+}
+
+impl std::str::FromStr for CosinePalette {
+    type Err = PaletteError;
+
+    //Accepts any separators, so things like vec3(0.5, 0.5, 0.5) copied from a shader work too
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers: Vec<f32> = s
+            .replace("vec3", " ")
+            .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        if numbers.len() != 12 {
+            return Err(PaletteError::Parse(format!(
+                "expected 12 numbers, got {}",
+                numbers.len()
+            )));
+        }
+        let vec3 = |i: usize| [numbers[i * 3], numbers[i * 3 + 1], numbers[i * 3 + 2]];
+        Ok(Self {
+            a: vec3(0),
+            b: vec3(1),
+            c: vec3(2),
+            d: vec3(3),
+        })
+    }
+}
+
+impl CosinePalette {
+    //The rainbow-ish one from the article
+    pub const DEFAULT: Self = Self {
+        a: [0.5, 0.5, 0.5],
+        b: [0.5, 0.5, 0.5],
+        c: [1.0, 1.0, 1.0],
+        d: [0.0, 0.33, 0.67],
+    };
+
+    //What the sliders for a, b, c and d go over. A negative b flips the wave, d past 1 is the
+    //same as its fractional part
+    pub const RANGES: [std::ops::RangeInclusive<f32>; 4] =
+        [0.0..=1.0, -1.0..=1.0, 0.0..=3.0, 0.0..=1.0];
+
+    pub fn raw(&self) -> [[f32; 3]; 4] {
+        [self.a, self.b, self.c, self.d]
+    }
+
+    pub fn sample(&self, t: f32) -> Color {
+        let channel = |i: usize| {
+            (self.a[i] + self.b[i] * (std::f32::consts::TAU * (self.c[i] * t + self.d[i])).cos())
+                .clamp(0.0, 1.0)
+        };
+        Color::from_rgb(channel(0), channel(1), channel(2))
+    }
+
+    //Random but good looking parameters, the same seed always gives the same palette
+    pub fn random(seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+        let mut vec3 = |min: f32, max: f32| {
+            [
+                rng.next_range(min, max),
+                rng.next_range(min, max),
+                rng.next_range(min, max),
+            ]
+        };
+        let a = vec3(0.35, 0.65);
+        let b = vec3(0.25, 0.5);
+        //Whole frequencies keep the palette seamless when it repeats
+        let frequency = rng.next_range(1.0, 2.999).floor();
+        let c = [frequency; 3];
+        //Phase offsets close to each other give analogous colors, spread out ones give triads
+        let base = rng.next_range(0.0, 1.0);
+        let spread = rng.next_range(0.05, 0.35);
+        let d = [base, base + spread, base + spread * 2.0].map(f32::fract);
+        Self { a, b, c, d }
+    }
+
+    pub fn to_palette(self, name: &str, stops: usize) -> Palette {
+        Palette::new(
+            name,
+            even_positions(stops)
+                .into_iter()
+                .map(|t| self.sample(t))
+                .collect(),
+        )
+    }
+}
+
+//Tiny PRNG so there's no need to pull in the rand crate, https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_range(&mut self, min: f32, max: f32) -> f32 {
+        //Top 24 bits are enough for an f32
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+}
+
+//Something to seed the random palettes with
+pub fn new_seed() -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    //Keep it short enough to be typed in by hand
+    SplitMix64(nanos).next() % 1_000_000
+}
+
+impl PaletteFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "fvp" => Some(Self::Native),
+            "map" => Some(Self::Map),
+            "ggr" => Some(Self::Ggr),
+            "ugr" => Some(Self::Ugr),
+            "hex" | "css" | "txt" => Some(Self::Hex),
+            _ => None,
+        }
+    }
+}
+
+pub fn color_hex(color: &Color) -> String {
+    let r_hex = format!("{:02x}", (color.r * 255.0) as u8);
+    let g_hex = format!("{:02x}", (color.g * 255.0) as u8);
+    let b_hex = format!("{:02x}", (color.b * 255.0) as u8);
+    let a_hex = format!("{:02x}", (color.a * 255.0) as u8);
+
+    format!("#{}{}{}{}", r_hex, g_hex, b_hex, a_hex)
+}
+
+//Accepts #rgb, #rrggbb and #rrggbbaa, with or without the #
+pub fn parse_hex(s: &str) -> Option<Color> {
+    let s = s.trim().trim_start_matches('#');
+    if !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize, len: usize| u8::from_str_radix(&s[i * len..(i + 1) * len], 16).ok();
+    match s.len() {
+        3 => Some(Color::from_rgb8(
+            channel(0, 1)? * 17,
+            channel(1, 1)? * 17,
+            channel(2, 1)? * 17,
+        )),
+        6 => Some(Color::from_rgb8(
+            channel(0, 2)?,
+            channel(1, 2)?,
+            channel(2, 2)?,
+        )),
+        8 => Some(Color::from_rgba8(
+            channel(0, 2)?,
+            channel(1, 2)?,
+            channel(2, 2)?,
+            channel(3, 2)? as f32 / 255.0,
+        )),
+        _ => None,
+    }
+}
+
+pub fn even_positions(len: usize) -> Vec<f32> {
+    (0..len).map(|i| i as f32 / len as f32).collect()
+}
+
+pub fn is_even(positions: &[f32]) -> bool {
+    even_positions(positions.len())
+        .iter()
+        .zip(positions)
+        .all(|(a, b)| (a - b).abs() < 1e-4)
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<Color>) -> Self {
+        Self {
+            name: name.to_string(),
+            positions: even_positions(colors.len()),
+            colors,
+        }
+    }
+
+    //Same math as get_col in the fragment shader, t is the position inside one repetition
+    pub fn sample(&self, t: f32) -> Color {
+        let len = self.colors.len();
+        if len == 0 {
+            return Color::BLACK;
+        }
+        let t = t.rem_euclid(1.0);
+        let prev = self
+            .positions
+            .iter()
+            .rposition(|p| *p <= t)
+            .unwrap_or(len - 1);
+        let next = (prev + 1) % len;
+        let mut start = self.positions[prev];
+        let mut end = self.positions[next];
+        if start > t {
+            start -= 1.0;
+        }
+        if end <= t {
+            end += 1.0;
+        }
+        let f = (t - start) / (end - start).max(0.00001);
+        let (a, b) = (self.colors[prev], self.colors[next]);
+        Color::from_rgba(
+            crate::lerp(a.r, b.r, f),
+            crate::lerp(a.g, b.g, f),
+            crate::lerp(a.b, b.b, f),
+            crate::lerp(a.a, b.a, f),
+        )
+    }
+
+    //Sorts the stops and makes sure they fit into the GPU buffer
+    pub(crate) fn normalized(mut self) -> Result<Self, PaletteError> {
+        if self.colors.is_empty() {
+            return Err(PaletteError::Parse("no colors found".into()));
+        }
+        for p in &mut self.positions {
+            *p = p.clamp(0.0, 1.0);
+        }
+        let mut stops: Vec<(f32, Color)> = self
+            .positions
+            .iter()
+            .copied()
+            .zip(self.colors.iter().copied())
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        //A stop at 1.0 is the same as the stop at 0.0 of the next repetition
+        if stops.len() > 1 && stops.last().unwrap().0 >= 1.0 {
+            let last = stops.pop().unwrap();
+            if stops[0].0 > 0.0 {
+                stops.insert(0, (0.0, last.1));
+            } else if stops[0].1 != last.1 {
+                //Keep the hard edge at the end of the palette
+                stops.push((0.9999, last.1));
+            }
+        }
+        //Drop identical neighbours, formats like ggr repeat every inner stop
+        stops.dedup_by(|b, a| a.0 == b.0 && a.1 == b.1);
+
+        if stops.len() > MAX_COLORS {
+            //Too many stops for the shader, resample evenly
+            self.positions = even_positions(MAX_COLORS);
+            let full = Self {
+                positions: stops.iter().map(|s| s.0).collect(),
+                colors: stops.iter().map(|s| s.1).collect(),
+                ..self.clone()
+            };
+            self.colors = self.positions.iter().map(|p| full.sample(*p)).collect();
+        } else {
+            self.positions = stops.iter().map(|s| s.0).collect();
+            self.colors = stops.iter().map(|s| s.1).collect();
+        }
+        Ok(self)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PaletteError> {
+        let format = PaletteFormat::from_path(path).ok_or(PaletteError::UnknownFormat)?;
+        let contents = fs::read_to_string(path)?;
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unnamed")
+            .to_string();
+        Self::parse(&contents, format, &name)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PaletteError> {
+        let format = PaletteFormat::from_path(path).ok_or(PaletteError::UnknownFormat)?;
+        fs::write(path, self.serialize(format))?;
+        Ok(())
+    }
+
+    pub fn parse(contents: &str, format: PaletteFormat, name: &str) -> Result<Self, PaletteError> {
+        let palette = match format {
+            PaletteFormat::Native => parse_native(contents),
+            PaletteFormat::Map => parse_map(contents),
+            PaletteFormat::Ggr => parse_ggr(contents),
+            PaletteFormat::Ugr => parse_ugr(contents),
+            PaletteFormat::Hex => parse_hex_list(contents),
+        }?;
+        Self {
+            name: if palette.name.is_empty() {
+                name.to_string()
+            } else {
+                palette.name
+            },
+            ..palette
+        }
+        .normalized()
+    }
+
+    pub fn serialize(&self, format: PaletteFormat) -> String {
+        match format {
+            PaletteFormat::Native => self.to_native(),
+            PaletteFormat::Map => self.to_map(),
+            PaletteFormat::Ggr => self.to_ggr(),
+            PaletteFormat::Ugr => self.to_ugr(),
+            PaletteFormat::Hex => self.to_hex_list(),
+        }
+    }
+
+    fn to_native(&self) -> String {
+        let mut out = format!("fractal_viewer palette\nname = {}\n", self.name);
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            _ = writeln!(out, "{p} {}", color_hex(c));
+        }
+        out
+    }
+
+    fn to_map(&self) -> String {
+        //Fractint maps are always 256 entries
+        let mut out = String::new();
+        for i in 0..256 {
+            let c = self.sample(i as f32 / 256.0);
+            _ = writeln!(out, "{} {} {}", to_u8(c.r), to_u8(c.g), to_u8(c.b));
+        }
+        out
+    }
+
+    fn to_ggr(&self) -> String {
+        let mut out = format!("GIMP Gradient\nName: {}\n", self.name);
+        //The palette wraps around, so close it with the first color at 1.0
+        let mut stops: Vec<(f32, Color)> = self
+            .positions
+            .iter()
+            .copied()
+            .zip(self.colors.iter().copied())
+            .collect();
+        if stops[0].0 > 0.0 {
+            stops.insert(0, (0.0, self.sample(0.0)));
+        }
+        stops.push((1.0, stops[0].1));
+        _ = writeln!(out, "{}", stops.len() - 1);
+        for s in stops.windows(2) {
+            let (l, r) = (s[0], s[1]);
+            _ = writeln!(
+                out,
+                "{:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} 0 0 0 0",
+                l.0,
+                (l.0 + r.0) * 0.5,
+                r.0,
+                l.1.r,
+                l.1.g,
+                l.1.b,
+                l.1.a,
+                r.1.r,
+                r.1.g,
+                r.1.b,
+                r.1.a
+            );
+        }
+        out
+    }
+
+    fn to_ugr(&self) -> String {
+        let title: String = self
+            .name
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+        //Quotes can't be escaped in there
+        let mut out = format!(
+            "{} {{\ngradient:\n  title=\"{}\" smooth=no\n",
+            title,
+            self.name.replace('"', "")
+        );
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            //Ultra Fractal stores colors as BGR integers
+            let color = to_u8(c.r) as u32 | (to_u8(c.g) as u32) << 8 | (to_u8(c.b) as u32) << 16;
+            //Index 400 is the same as 0, so anything right at the end stays at 399
+            _ = writeln!(
+                out,
+                "  index={} color={}",
+                ((p * 400.0).round() as u32).min(399),
+                color
+            );
+        }
+        out.push_str("opacity:\n  smooth=no index=0 opacity=255\n}\n");
+        out
+    }
+
+    fn to_hex_list(&self) -> String {
+        let mut out = String::new();
+        let even = is_even(&self.positions);
+        for (p, c) in self.positions.iter().zip(&self.colors) {
+            if even {
+                _ = writeln!(out, "{}", color_hex(c));
+            } else {
+                _ = writeln!(out, "{} {}%", color_hex(c), p * 100.0);
+            }
+        }
+        out
+    }
+}
+
+fn parse_native(contents: &str) -> Result<Palette, PaletteError> {
+    let mut lines = contents.lines();
+    if lines.next().map(str::trim) != Some("fractal_viewer palette") {
+        return Err(PaletteError::Parse("missing header".into()));
+    }
+    let mut palette = Palette::new("", Vec::new());
+    for line in lines.map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix("name") {
+            palette.name = name.trim_start().trim_start_matches('=').trim().to_string();
+            continue;
+        }
+        let (pos, color) = line
+            .split_once(' ')
+            .ok_or_else(|| PaletteError::Parse(format!("bad line \"{line}\"")))?;
+        palette.positions.push(
+            pos.parse()
+                .map_err(|_| PaletteError::Parse(format!("bad position \"{pos}\"")))?,
+        );
+        palette.colors.push(
+            parse_hex(color)
+                .ok_or_else(|| PaletteError::Parse(format!("bad color \"{color}\"")))?,
+        );
+    }
+    Ok(palette)
+}
+
+fn parse_map(contents: &str) -> Result<Palette, PaletteError> {
+    let colors = contents
+        .lines()
+        .filter_map(|line| {
+            //Anything after the third number is a comment
+            let mut numbers = line.split_whitespace().map(|n| n.parse::<u8>());
+            match (numbers.next(), numbers.next(), numbers.next()) {
+                (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some(Color::from_rgb8(r, g, b)),
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(Palette::new("", colors))
+}
+
+fn parse_ggr(contents: &str) -> Result<Palette, PaletteError> {
+    let mut lines = contents.lines().map(str::trim);
+    if lines.next() != Some("GIMP Gradient") {
+        return Err(PaletteError::Parse("missing header".into()));
+    }
+    let mut palette = Palette::new("", Vec::new());
+    let mut count = None;
+    for line in lines.filter(|l| !l.is_empty()) {
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette.name = name.trim().to_string();
+        } else if count.is_none() {
+            count = Some(
+                line.parse::<usize>()
+                    .map_err(|_| PaletteError::Parse("bad segment count".into()))?,
+            );
+        } else {
+            let v: Vec<f32> = line
+                .split_whitespace()
+                .take(11)
+                .map(|n| n.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| PaletteError::Parse(format!("bad segment \"{line}\"")))?;
+            if v.len() < 11 {
+                return Err(PaletteError::Parse(format!("bad segment \"{line}\"")));
+            }
+            //Midpoints and blending functions are approximated with linear blending
+            palette.positions.push(v[0]);
+            palette
+                .colors
+                .push(Color::from_rgba(v[3], v[4], v[5], v[6]));
+            palette.positions.push(v[2]);
+            palette
+                .colors
+                .push(Color::from_rgba(v[7], v[8], v[9], v[10]));
+        }
+    }
+    Ok(palette)
+}
+
+//The key=value pairs on a line of a ugr file, quoted values can have spaces in them
+fn key_values(line: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = line;
+    while let Some((before, after)) = rest.split_once('=') {
+        //Words without an = in front of the key don't count
+        let key = before.split_whitespace().last().unwrap_or("");
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        pairs.push((key, value));
+        rest = next;
+    }
+    pairs
+}
+
+fn parse_ugr(contents: &str) -> Result<Palette, PaletteError> {
+    let mut palette = Palette::new("", Vec::new());
+    let mut in_gradient = false;
+    for line in contents.lines().map(str::trim) {
+        match line {
+            "gradient:" => in_gradient = true,
+            "opacity:" | "}" => {
+                //Only the first gradient in the file is imported
+                if in_gradient && !palette.colors.is_empty() {
+                    break;
+                }
+                in_gradient = false;
+            }
+            _ if in_gradient => {
+                let mut index = None;
+                let mut color = None;
+                for (key, value) in key_values(line) {
+                    match key {
+                        "title" => palette.name = value.to_string(),
+                        "index" => index = value.parse::<i32>().ok(),
+                        "color" => color = value.parse::<u32>().ok(),
+                        _ => {}
+                    }
+                }
+                if let (Some(i), Some(c)) = (index, color) {
+                    palette.positions.push(i.rem_euclid(400) as f32 / 400.0);
+                    palette.colors.push(Color::from_rgb8(
+                        (c & 0xff) as u8,
+                        (c >> 8 & 0xff) as u8,
+                        (c >> 16 & 0xff) as u8,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(palette)
+}
+
+fn parse_hex_list(contents: &str) -> Result<Palette, PaletteError> {
+    let mut colors = Vec::new();
+    let mut positions = Vec::new();
+    //Split on anything that can't be part of a color or a percentage
+    let tokens: Vec<&str> = contents
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '#' || c == '%' || c == '.'))
+        .filter(|t| !t.is_empty())
+        .collect();
+    for (i, token) in tokens.iter().enumerate() {
+        if !token.starts_with('#') {
+            continue;
+        }
+        let Some(color) = parse_hex(token) else {
+            continue;
+        };
+        colors.push(color);
+        //css gradients can have a position right after the color
+        positions.push(
+            tokens
+                .get(i + 1)
+                .and_then(|t| t.strip_suffix('%'))
+                .and_then(|t| t.parse::<f32>().ok())
+                .map(|p| p / 100.0),
+        );
+    }
+    let positions = if positions.iter().all(Option::is_some) {
+        positions.into_iter().map(Option::unwrap).collect()
+    } else {
+        even_positions(colors.len())
+    };
+    Ok(Palette {
+        name: String::new(),
+        colors,
+        positions,
+    })
+}
+
+//Scans the palette library directory, returns nothing if it doesn't exist
+pub fn scan_library() -> Vec<LibraryEntry> {
+    let Ok(dir) = fs::read_dir(PALETTE_DIR) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<LibraryEntry> = dir
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && PaletteFormat::from_path(p).is_some())
+        .map(|path| LibraryEntry {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path,
+        })
+        .collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Every channel is a whole 8 bit value so formats that store bytes give the same colors back
+    fn test_palette() -> Palette {
+        Palette {
+            name: "My Palette".into(),
+            colors: vec![
+                Color::from_rgb8(255, 0, 0),
+                Color::from_rgb8(20, 200, 100),
+                Color::from_rgb8(0, 0, 255),
+            ],
+            positions: vec![0.0, 0.25, 0.6],
+        }
+    }
+
+    fn assert_close(a: &Palette, b: &Palette, tolerance: f32) {
+        assert_eq!(a.colors.len(), b.colors.len(), "{a:?} {b:?}");
+        for (x, y) in a.positions.iter().zip(&b.positions) {
+            assert!((x - y).abs() <= tolerance, "{a:?} {b:?}");
+        }
+        for (x, y) in a.colors.iter().zip(&b.colors) {
+            let channels = [x.r - y.r, x.g - y.g, x.b - y.b, x.a - y.a];
+            assert!(channels.iter().all(|c| c.abs() <= tolerance), "{a:?} {b:?}");
+        }
+    }
+
+    fn round_trip(palette: &Palette, format: PaletteFormat) -> Palette {
+        Palette::parse(&palette.serialize(format), format, "Fallback").unwrap()
+    }
+
+    #[test]
+    fn native_round_trip() {
+        let palette = test_palette();
+        assert_eq!(round_trip(&palette, PaletteFormat::Native), palette);
+    }
+
+    #[test]
+    fn ggr_round_trip() {
+        let palette = test_palette();
+        let parsed = round_trip(&palette, PaletteFormat::Ggr);
+        assert_eq!(parsed.name, palette.name);
+        assert_close(&parsed, &palette, 1e-5);
+    }
+
+    #[test]
+    fn ugr_round_trip() {
+        let palette = test_palette();
+        let parsed = round_trip(&palette, PaletteFormat::Ugr);
+        assert_eq!(parsed.name, "My Palette");
+        assert_close(&parsed, &palette, 1.0 / 400.0);
+    }
+
+    #[test]
+    fn ugr_keeps_last_stop_at_the_end() {
+        let palette = Palette {
+            positions: vec![0.0, 0.5, 0.9999],
+            ..test_palette()
+        };
+        let parsed = round_trip(&palette, PaletteFormat::Ugr);
+        assert_eq!(parsed.colors, palette.colors);
+        assert_eq!(parsed.positions[2], 399.0 / 400.0);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let palette = test_palette();
+        let parsed = round_trip(&palette, PaletteFormat::Hex);
+        assert_close(&parsed, &palette, 1e-5);
+
+        let even = Palette::new("Even", palette.colors.clone());
+        let parsed = round_trip(&even, PaletteFormat::Hex);
+        assert_eq!(parsed.colors, even.colors);
+        assert_eq!(parsed.positions, even.positions);
+    }
+
+    #[test]
+    fn map_round_trip() {
+        let palette = test_palette();
+        let parsed = round_trip(&palette, PaletteFormat::Map);
+        //Maps are sampled into 256 colors, so compare what the gradients look like
+        for i in 0..64 {
+            let t = i as f32 / 64.0;
+            let (a, b) = (palette.sample(t), parsed.sample(t));
+            for c in [a.r - b.r, a.g - b.g, a.b - b.b] {
+                assert!(c.abs() <= 2.0 / 255.0, "{t}: {a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn css_gradient() {
+        let parsed = Palette::parse(
+            "linear-gradient(90deg, #ff0000 0%, #00ff00 50%, #0000ff 75%)",
+            PaletteFormat::Hex,
+            "Css",
+        )
+        .unwrap();
+        assert_eq!(parsed.name, "Css");
+        assert_eq!(parsed.positions, vec![0.0, 0.5, 0.75]);
+        assert_eq!(parsed.colors[1], Color::from_rgb8(0, 255, 0));
+    }
+
+    #[test]
+    fn ugr_quoted_values() {
+        assert_eq!(
+            key_values("title=\"Two words\" smooth=no index=3"),
+            vec![("title", "Two words"), ("smooth", "no"), ("index", "3")]
+        );
+    }
+
+    #[test]
+    fn cosine_round_trip() {
+        let cosine = CosinePalette::random(12345);
+        assert_eq!(cosine.to_string().parse::<CosinePalette>().unwrap(), cosine);
+    }
+
+    #[test]
+    fn random_cosine_fits_the_sliders() {
+        for seed in 0..1000 {
+            let raw = CosinePalette::random(seed).raw();
+            for (values, range) in raw.into_iter().zip(CosinePalette::RANGES) {
+                assert!(values.iter().all(|v| range.contains(v)), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let parse = |contents, format| Palette::parse(contents, format, "Broken").is_err();
+        assert!(parse("", PaletteFormat::Native));
+        assert!(parse("not a palette\n0 #ff0000", PaletteFormat::Native));
+        assert!(parse("fractal_viewer palette\n0.5", PaletteFormat::Native));
+        assert!(parse(
+            "fractal_viewer palette\nx #ff0000",
+            PaletteFormat::Native
+        ));
+        assert!(parse(
+            "fractal_viewer palette\n0 #zzzzzz",
+            PaletteFormat::Native
+        ));
+        assert!(parse(
+            "fractal_viewer palette\nname = Empty",
+            PaletteFormat::Native
+        ));
+        assert!(parse("GIMP Gradient\nName: x\nmany", PaletteFormat::Ggr));
+        assert!(parse("GIMP Gradient\n1\n0 0.5 1 0 0", PaletteFormat::Ggr));
+        assert!(parse("Gradient\n1", PaletteFormat::Ggr));
+        assert!(parse("hello world", PaletteFormat::Map));
+        assert!(parse("x {\nopacity:\n}", PaletteFormat::Ugr));
+        assert!(parse("no colors here", PaletteFormat::Hex));
+        assert!("1 2 3".parse::<CosinePalette>().is_err());
+    }
+}
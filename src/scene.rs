@@ -1,7 +1,22 @@
+use std::ops::Range;
+
 use bytemuck::{Pod, Zeroable};
 use iced_wgpu::wgpu::{self, util::DeviceExt, BindGroup, Buffer};
 use wgpu::RenderPipeline;
 
+use crate::{
+    accumulation,
+    coloring::{self, Layer, LAYER_SIZE, MAX_LAYERS},
+    controls::Fractals,
+    iteration_cache::IterationCache,
+    tiles::{self, TILE_SIZE},
+};
+
+//Maximum number of color stops a single layer can have
+pub const MAX_COLORS: usize = 256;
+//Each stop is a vec4 color followed by its position, padded to 8 floats
+pub const STOP_SIZE: usize = 8;
+
 //Make memory layout the same as in C
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable, Default)]
@@ -9,49 +24,135 @@ pub struct ShaderDataUniforms {
     pub position: [f32; 2],
     pub resolution: [u32; 2],
     pub aspect: f32,
-    pub dummy: f32,
+    //In radians
+    pub rotation: f32,
     pub zoom: f32,
-    pub arr_len: u32,
+    pub num_layers: u32,
     pub fractal: u32,
     pub max_iter: u32,
-    pub num_colors: u32,
     pub msaa: u32,
+    //Index of the first sample, so progressive passes each get a different jitter
+    pub sample: u32,
+    //How close z has to come back to itself to count as a cycle, 0 turns period checking off
+    pub period_tolerance: f32,
+    //Sample pattern from antialiasing::pattern_flags
+    pub aa_pattern: u32,
+    pub aa_filter: u32,
+    //Samples the whole picture gets, across all the progressive passes
+    pub aa_samples: u32,
+    //Color difference it takes for a pixel to get more samples, 0 turns adaptive sampling off
+    pub adaptive_threshold: f32,
 }
 impl ShaderDataUniforms {
-    pub fn to_uniform_data(self) -> [u32; 12] {
+    pub fn to_uniform_data(self) -> [u32; 18] {
         [
             self.position[0].to_bits(),
             self.position[1].to_bits(),
             self.resolution[0],
             self.resolution[1],
             self.aspect.to_bits(),
-            //Padding cause it wasn't working w/o it
-            0,
+            self.rotation.to_bits(),
             self.zoom.to_bits(),
-            self.arr_len,
+            self.num_layers,
             self.fractal,
             self.max_iter,
-            self.num_colors,
             self.msaa,
+            self.sample,
+            self.period_tolerance.to_bits(),
+            self.aa_pattern,
+            self.aa_filter,
+            self.aa_samples,
+            self.adaptive_threshold.to_bits(),
+            //The struct gets padded to a multiple of 8 bytes in the shader
+            0,
         ]
     }
 }
 
+//The top bit of the fractal uniform turns on smooth coloring
+pub fn fractal_flags(fractal: Fractals, smooth: bool) -> u32 {
+    fractal as u32 | if smooth { 2147483648 } else { 0 }
+}
+
+//What capture_float renders into
+const EXPORT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+//The compute shader works on squares of this many pixels
+const WORKGROUP_SIZE: u32 = 8;
+
+struct Pipelines {
+    main: RenderPipeline,
+    //Same thing but rendering into an RGBA texture for screenshots
+    capture: RenderPipeline,
+    //Linear and unclamped into a float texture, for screenshots in high bit depth formats
+    export: RenderPipeline,
+    //Blends each pass into the accumulation texture
+    accumulate: RenderPipeline,
+    //Same blending, but colors what's in the iteration cache instead of iterating
+    color: RenderPipeline,
+    //Colors the iteration cache onto the screen
+    show_cache: RenderPipeline,
+    //Fills the iteration cache
+    compute: wgpu::ComputePipeline,
+}
+
 pub struct Scene {
-    pipeline: RenderPipeline,
+    pipelines: Pipelines,
+    capture_format: wgpu::TextureFormat,
+    cache: IterationCache,
     pub bind_group: BindGroup,
     pub buffer: Buffer,
+    //The minimap draws the same thing with its own uniforms
+    minimap_bind_group: BindGroup,
+    pub minimap_buffer: Buffer,
     pub storage_buffer: Buffer,
+    pub layer_buffer: Buffer,
 }
 
 impl Scene {
-    pub fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat) -> Self {
-        let (pipeline, buffer, storage_buffer, bind_group) = build_pipeline(device, texture_format);
+    pub fn new(device: &wgpu::Device, texture_format: wgpu::TextureFormat, size: [u32; 2]) -> Self {
+        //Screenshots should look the same as the window, so keep the same color space
+        let capture_format = if texture_format.describe().srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let cache = IterationCache::new(device, size);
+        let (pipelines, buffer, storage_buffer, layer_buffer, bind_group) =
+            build_pipeline(device, texture_format, capture_format, &cache);
+        let minimap_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Minimap uniform"),
+            contents: bytemuck::cast_slice(&ShaderDataUniforms::default().to_uniform_data()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let minimap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipelines.main.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: minimap_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: storage_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: layer_buffer.as_entire_binding(),
+                },
+            ],
+        });
         Self {
-            pipeline,
+            pipelines,
+            capture_format,
+            cache,
             bind_group,
             buffer,
+            minimap_bind_group,
+            minimap_buffer,
             storage_buffer,
+            layer_buffer,
         }
     }
 
@@ -80,34 +181,388 @@ impl Scene {
         })
     }
 
+    //The iteration cache has to match the window
+    pub fn resize(&mut self, device: &wgpu::Device, size: [u32; 2]) {
+        self.cache.resize(device, size);
+    }
+
     pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
-        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_pipeline(&self.pipelines.main);
         render_pass.draw(0..6, 0..1);
     }
+
+    //Gets the iteration cache ready to be filled again, panning passes a shift so only the
+    //pixels that came into view get iterated, see IterationCache::shift
+    pub fn shift_cache(&mut self, queue: &wgpu::Queue, shift: Option<[i32; 2]>) {
+        self.cache.shift(queue, shift);
+    }
+
+    //How many pixels the last iterating pass did and how many ran out of iterations
+    pub fn iteration_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> [u32; 2] {
+        self.cache.read_stats(device, queue)
+    }
+
+    //Iterates one sample of every pixel in some tiles into the iteration cache, at the
+    //resolution and with the sample in the uniforms. Needs a submission of its own
+    pub fn compute(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        tiles: Range<u32>,
+    ) {
+        self.cache.write_uniforms(queue, tiles.start);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Iterate"),
+        });
+        compute_pass.set_pipeline(&self.pipelines.compute);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.cache.write_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            TILE_SIZE / WORKGROUP_SIZE,
+            TILE_SIZE / WORKGROUP_SIZE,
+            tiles.len() as u32,
+        );
+    }
+
+    //Colors the tiles of the iteration cache that are done straight onto the screen, so they
+    //show up over the old picture while the rest are still being iterated
+    pub fn draw_tiles<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        size: [u32; 2],
+        tiles: Range<u32>,
+        target_size: [u32; 2],
+    ) {
+        render_pass.set_pipeline(&self.pipelines.show_cache);
+        render_pass.set_bind_group(1, &self.cache.read_bind_group, &[]);
+        for [x, y, width, height] in tiles::rects(size, tiles) {
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.draw(0..6, 0..1);
+        }
+        render_pass.set_scissor_rect(0, 0, target_size[0], target_size[1]);
+    }
+
+    //Blends one more pass into the accumulation texture, making up `weight` of the average
+    //afterwards, for some of the tiles. Only the top left `size` pixels get drawn. Cached passes
+    //color what's in the iteration cache instead of iterating
+    pub fn accumulate(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        size: [u32; 2],
+        weight: f64,
+        cached: bool,
+        tiles: Range<u32>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Accumulate"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_blend_constant(wgpu::Color {
+            r: weight,
+            g: weight,
+            b: weight,
+            a: weight,
+        });
+        render_pass.set_viewport(0.0, 0.0, size[0] as f32, size[1] as f32, 0.0, 1.0);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        //Iterating passes look at the cache too, for where the edges are
+        render_pass.set_bind_group(1, &self.cache.read_bind_group, &[]);
+        if cached {
+            render_pass.set_pipeline(&self.pipelines.color);
+        } else {
+            render_pass.set_pipeline(&self.pipelines.accumulate);
+        }
+        for [x, y, width, height] in tiles::rects(size, tiles) {
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+
+    //Draws into a small part of the target using the minimap uniforms, then puts the viewport
+    //back to the whole target for whatever comes next
+    pub fn draw_minimap<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        origin: [f32; 2],
+        size: [f32; 2],
+        target_size: [u32; 2],
+    ) {
+        render_pass.set_viewport(origin[0], origin[1], size[0], size[1], 0.0, 1.0);
+        render_pass.set_bind_group(0, &self.minimap_bind_group, &[]);
+        self.draw(render_pass);
+        render_pass.set_viewport(
+            0.0,
+            0.0,
+            target_size[0] as f32,
+            target_size[1] as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+    }
+
+    //Renders the fractal without the UI at the resolution in the uniforms and reads it back as
+    //tightly packed RGBA rows. It goes in tiles so a big export with lots of samples doesn't
+    //make the driver think the GPU hung. This overwrites the buffers, the next frame puts them
+    //back
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniforms: ShaderDataUniforms,
+        layers: &[Layer],
+    ) -> Vec<u8> {
+        self.render_offscreen(
+            device,
+            queue,
+            uniforms,
+            layers,
+            &self.pipelines.capture,
+            self.capture_format,
+        )
+    }
+
+    //Same but with linear 32 bit floats that can go past 1
+    pub fn capture_float(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniforms: ShaderDataUniforms,
+        layers: &[Layer],
+    ) -> Vec<f32> {
+        let bytes = self.render_offscreen(
+            device,
+            queue,
+            uniforms,
+            layers,
+            &self.pipelines.export,
+            EXPORT_FORMAT,
+        );
+        bytemuck::pod_collect_to_vec(&bytes)
+    }
+
+    fn render_offscreen(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniforms: ShaderDataUniforms,
+        layers: &[Layer],
+        pipeline: &RenderPipeline,
+        format: wgpu::TextureFormat,
+    ) -> Vec<u8> {
+        let [width, height] = uniforms.resolution;
+        let (raw_layers, raw_colors) = coloring::layers_raw(layers);
+        let uniforms = ShaderDataUniforms {
+            num_layers: (raw_layers.len() / LAYER_SIZE) as u32,
+            ..uniforms
+        };
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&uniforms.to_uniform_data()),
+        );
+        queue.write_buffer(&self.storage_buffer, 0, bytemuck::cast_slice(&raw_colors));
+        queue.write_buffer(&self.layer_buffer, 0, bytemuck::cast_slice(&raw_layers));
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        //Rows have to be padded to 256 bytes when copying into a buffer
+        let row_size = width * format.describe().block_size as u32;
+        let padded_row_size = row_size.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot readback"),
+            size: (padded_row_size * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.clear(&view, &mut encoder);
+        queue.submit(Some(encoder.finish()));
+        //Nothing else is drawn while it's going, so it just keeps at it until every tile is done
+        let mut scheduler = tiles::Scheduler::default();
+        let count = tiles::count(uniforms.resolution);
+        let mut done = 0;
+        while done < count {
+            done = scheduler.run(device, queue, done..count, |encoder, tiles| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Export"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                render_pass.set_bind_group(0, &self.bind_group, &[]);
+                render_pass.set_pipeline(pipeline);
+                for [x, y, width, height] in tiles::rects(uniforms.resolution, tiles) {
+                    render_pass.set_scissor_rect(x, y, width, height);
+                    render_pass.draw(0..6, 0..1);
+                }
+            });
+        }
+
+        //Read back once it's all there
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            extent,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((row_size * height) as usize);
+        for row in data.chunks(padded_row_size as usize) {
+            pixels.extend_from_slice(&row[..row_size as usize]);
+        }
+        drop(data);
+        buffer.unmap();
+        pixels
+    }
+}
+
+//A shader that draws onto a target of this format, with the color space code from srgb.wgsl in
+//front of it
+pub fn output_module(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    source: &str,
+) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(
+            format!(
+                "let ENCODE_SRGB: bool = {};\n{}{source}",
+                !format.describe().srgb,
+                include_str!("shader/srgb.wgsl")
+            )
+            .into(),
+        ),
+    })
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    fs_entry_point: &str,
+    texture_format: wgpu::TextureFormat,
+    //Float32 formats can't blend at all
+    blend: Option<wgpu::BlendState>,
+) -> RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vs_module,
+            entry_point: "main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: fs_module,
+            entry_point: fs_entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format: texture_format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            ..Default::default()
+        },
+        //No need for depth
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
 }
 
 fn build_pipeline(
     device: &wgpu::Device,
     texture_format: wgpu::TextureFormat,
-) -> (RenderPipeline, Buffer, Buffer, BindGroup) {
-    //Shaders
-    let (vs_module, fs_module) = (
-        device.create_shader_module(wgpu::include_wgsl!("shader/vert.wgsl")),
-        device.create_shader_module(wgpu::include_wgsl!("shader/frag.wgsl")),
-    );
+    capture_format: wgpu::TextureFormat,
+    cache: &IterationCache,
+) -> (Pipelines, Buffer, Buffer, Buffer, BindGroup) {
+    //Shaders, the fractal ones all start with the same code. Screenshots are sRGB whenever the
+    //screen is so the same code works for both
+    let fractal_module = |label, source: &str| {
+        output_module(
+            device,
+            label,
+            texture_format,
+            &format!("{}{source}", include_str!("shader/fractal.wgsl")),
+        )
+    };
+    let vs_module = device.create_shader_module(wgpu::include_wgsl!("shader/vert.wgsl"));
+    let fs_module = fractal_module("frag.wgsl", include_str!("shader/frag.wgsl"));
+    let color_module = fractal_module("color.wgsl", include_str!("shader/color.wgsl"));
+    let compute_module = fractal_module("compute.wgsl", include_str!("shader/compute.wgsl"));
 
     //Uniform buffer creation
     let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Uniform"),
-        contents: bytemuck::cast_slice(&[ShaderDataUniforms::default()]),
+        contents: bytemuck::cast_slice(&ShaderDataUniforms::default().to_uniform_data()),
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    //Storage buffer for the color array
+    //Storage buffer for the color stop array
     let storage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Storage"),
-        //256 floats = 64 vec4s = 64 colors
-        contents: bytemuck::cast_slice(&[0.0; 256]),
+        contents: bytemuck::cast_slice(&vec![0.0f32; MAX_LAYERS * MAX_COLORS * STOP_SIZE]),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    });
+
+    //Storage buffer for the coloring layers
+    let layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Layers"),
+        contents: bytemuck::cast_slice(&[0u32; MAX_LAYERS * LAYER_SIZE]),
         usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
     });
 
@@ -116,7 +571,9 @@ fn build_pipeline(
         entries: &[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::FRAGMENT
+                    | wgpu::ShaderStages::VERTEX
+                    | wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -126,7 +583,17 @@ fn build_pipeline(
             },
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
                     has_dynamic_offset: false,
@@ -149,6 +616,10 @@ fn build_pipeline(
                 binding: 1,
                 resource: storage_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: layer_buffer.as_entire_binding(),
+            },
         ],
     });
 
@@ -159,43 +630,96 @@ fn build_pipeline(
         push_constant_ranges: &[],
     });
 
-    (
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &vs_module,
-                entry_point: "main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &fs_module,
-                entry_point: "main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: texture_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                front_face: wgpu::FrontFace::Ccw,
-                ..Default::default()
-            },
-            //No need for depth
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+    let color_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout, &cache.read_layout],
+        push_constant_ranges: &[],
+    });
+    let compute_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout, &cache.write_layout],
+        push_constant_ranges: &[],
+    });
+
+    //new * weight + old * (1 - weight) is a running average with the right weight. Alpha has
+    //the filter weights, it gets averaged the same way and the color divided by it at the end
+    let average = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Constant,
+        dst_factor: wgpu::BlendFactor::OneMinusConstant,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let accumulate_blend = wgpu::BlendState {
+        color: average,
+        alpha: average,
+    };
+    let pipelines = Pipelines {
+        main: create_render_pipeline(
+            device,
+            &pipeline_layout,
+            &vs_module,
+            &fs_module,
+            "main",
+            texture_format,
+            Some(wgpu::BlendState::REPLACE),
+        ),
+        capture: create_render_pipeline(
+            device,
+            &pipeline_layout,
+            &vs_module,
+            &fs_module,
+            "main",
+            capture_format,
+            Some(wgpu::BlendState::REPLACE),
+        ),
+        export: create_render_pipeline(
+            device,
+            &pipeline_layout,
+            &vs_module,
+            &fs_module,
+            "float_output",
+            EXPORT_FORMAT,
+            None,
+        ),
+        accumulate: create_render_pipeline(
+            device,
+            &color_layout,
+            &vs_module,
+            &fs_module,
+            "accumulate",
+            accumulation::FORMAT,
+            Some(accumulate_blend),
+        ),
+        color: create_render_pipeline(
+            device,
+            &color_layout,
+            &vs_module,
+            &color_module,
+            "main",
+            accumulation::FORMAT,
+            Some(accumulate_blend),
+        ),
+        show_cache: create_render_pipeline(
+            device,
+            &color_layout,
+            &vs_module,
+            &color_module,
+            "show",
+            texture_format,
+            Some(wgpu::BlendState::REPLACE),
+        ),
+        compute: device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Iterate"),
+            layout: Some(&compute_layout),
+            module: &compute_module,
+            entry_point: "main",
         }),
+    };
+
+    (
+        pipelines,
         uniform_buffer,
         storage_buffer,
+        layer_buffer,
         bind_group,
     )
 }
@@ -0,0 +1,337 @@
+use std::{fmt::Write as _, fs, path::Path, str::FromStr};
+
+use crate::{
+    coloring::{Algorithm, BlendMode, Layer, Transfer},
+    controls::{Fractals, View, MAX_MSAA},
+    iterations::MAX_ITERATIONS,
+    palette::{color_hex, parse_hex, CosinePalette, Palette, PaletteMode},
+};
+
+const HEADER: &str = "fractal_viewer scene";
+
+//Everything needed to get back to the same image
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedScene {
+    pub fractal: Fractals,
+    pub num_iters: u32,
+    pub smooth_enabled: bool,
+    pub msaa: u32,
+    pub view: View,
+    //In degrees
+    pub rotation: f32,
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(String),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Parse(e) => write!(f, "Invalid scene: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+//All the enums are written with their Display names
+fn parse_enum<T: std::fmt::Display + Copy>(all: &[T], value: &str) -> Result<T, SceneError> {
+    all.iter()
+        .find(|v| v.to_string() == value)
+        .copied()
+        .ok_or_else(|| SceneError::Parse(format!("unknown value \"{value}\"")))
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, SceneError> {
+    value
+        .parse()
+        .map_err(|_| SceneError::Parse(format!("bad value \"{value}\"")))
+}
+
+impl SavedScene {
+    pub fn save(&self, path: &Path) -> Result<(), SceneError> {
+        fs::write(path, self.serialize())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn serialize(&self) -> String {
+        format!("{HEADER}\n{}", self.serialize_body())
+    }
+
+    //Everything but the header, so scenes can be embedded in other files
+    pub fn serialize_body(&self) -> String {
+        let mut out = String::new();
+        _ = writeln!(out, "fractal = {}", self.fractal);
+        _ = writeln!(out, "num_iters = {}", self.num_iters);
+        _ = writeln!(out, "smooth = {}", self.smooth_enabled);
+        _ = writeln!(out, "msaa = {}", self.msaa);
+        _ = writeln!(
+            out,
+            "position = {} {}",
+            self.view.position[0], self.view.position[1]
+        );
+        _ = writeln!(out, "zoom = {}", self.view.zoom);
+        _ = writeln!(out, "rotation = {}", self.rotation);
+        for layer in &self.layers {
+            out.push_str("\n[layer]\n");
+            _ = writeln!(out, "algorithm = {}", layer.algorithm);
+            _ = writeln!(out, "transfer = {}", layer.transfer);
+            _ = writeln!(out, "blend = {}", layer.blend);
+            _ = writeln!(out, "opacity = {}", layer.opacity);
+            _ = writeln!(out, "param = {}", layer.param);
+            _ = writeln!(out, "num_colors = {}", layer.num_colors);
+            _ = writeln!(out, "palette_mode = {}", layer.palette_mode);
+            _ = writeln!(out, "cosine = {}", layer.cosine);
+            _ = writeln!(out, "palette = {}", layer.palette.name);
+            for (p, c) in layer.palette.positions.iter().zip(&layer.palette.colors) {
+                _ = writeln!(out, "stop = {p} {}", color_hex(c));
+            }
+        }
+        out
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, SceneError> {
+        let mut lines = contents.lines().map(str::trim);
+        if lines.next() != Some(HEADER) {
+            return Err(SceneError::Parse("missing header".into()));
+        }
+        Self::parse_body(lines)
+    }
+
+    pub fn parse_body<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Self, SceneError> {
+        let mut scene = Self {
+            fractal: Fractals::default(),
+            num_iters: 1000,
+            smooth_enabled: false,
+            msaa: 1,
+            view: View {
+                position: [0.0, 0.0],
+                zoom: 500.0,
+            },
+            rotation: 0.0,
+            layers: Vec::new(),
+        };
+        for line in lines.filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            if line == "[layer]" {
+                scene.layers.push(Layer {
+                    palette: Palette::new("", Vec::new()),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| SceneError::Parse(format!("bad line \"{line}\"")))?;
+            match scene.layers.last_mut() {
+                None => match key {
+                    "fractal" => scene.fractal = parse_enum(&Fractals::ALL, value)?,
+                    "num_iters" => scene.num_iters = parse_value(value)?,
+                    "smooth" => scene.smooth_enabled = parse_value(value)?,
+                    "msaa" => scene.msaa = parse_value(value)?,
+                    "position" => {
+                        let (x, y) = value
+                            .split_once(' ')
+                            .ok_or_else(|| SceneError::Parse("bad position".into()))?;
+                        scene.view.position = [parse_value(x)?, parse_value(y)?];
+                    }
+                    "zoom" => scene.view.zoom = parse_value(value)?,
+                    "rotation" => scene.rotation = parse_value(value)?,
+                    _ => return Err(SceneError::Parse(format!("unknown key \"{key}\""))),
+                },
+                Some(layer) => match key {
+                    "algorithm" => layer.algorithm = parse_enum(&Algorithm::ALL, value)?,
+                    "transfer" => layer.transfer = parse_enum(&Transfer::ALL, value)?,
+                    "blend" => layer.blend = parse_enum(&BlendMode::ALL, value)?,
+                    "opacity" => layer.opacity = parse_value(value)?,
+                    "param" => layer.param = parse_value(value)?,
+                    "num_colors" => layer.num_colors = parse_value(value)?,
+                    "palette_mode" => {
+                        layer.palette_mode =
+                            parse_enum(&[PaletteMode::Stops, PaletteMode::Cosine], value)?
+                    }
+                    "cosine" => {
+                        layer.cosine = value
+                            .parse::<CosinePalette>()
+                            .map_err(|e| SceneError::Parse(e.to_string()))?
+                    }
+                    "palette" => layer.palette.name = value.to_string(),
+                    "stop" => {
+                        let (p, c) = value
+                            .split_once(' ')
+                            .ok_or_else(|| SceneError::Parse(format!("bad stop \"{value}\"")))?;
+                        layer.palette.positions.push(parse_value(p)?);
+                        layer.palette.colors.push(
+                            parse_hex(c)
+                                .ok_or_else(|| SceneError::Parse(format!("bad color \"{c}\"")))?,
+                        );
+                    }
+                    _ => return Err(SceneError::Parse(format!("unknown key \"{key}\""))),
+                },
+            }
+        }
+        if scene.layers.is_empty() {
+            return Err(SceneError::Parse("no layers".into()));
+        }
+        scene.layers.truncate(crate::coloring::MAX_LAYERS);
+        scene.check()?;
+        //Hand edited stops get sorted and fitted to the shader like a loaded palette
+        for layer in &mut scene.layers {
+            let palette = std::mem::replace(&mut layer.palette, Palette::new("", Vec::new()));
+            layer.palette = palette
+                .normalized()
+                .map_err(|e| SceneError::Parse(e.to_string()))?;
+        }
+        Ok(scene)
+    }
+
+    //Numbers that parse fine but would break the renderer
+    fn check(&self) -> Result<(), SceneError> {
+        let fail = |what: &str| Err(SceneError::Parse(format!("{what} out of range")));
+        if !(1..=MAX_ITERATIONS).contains(&self.num_iters) {
+            return fail("num_iters");
+        }
+        if !(1..=MAX_MSAA).contains(&self.msaa) {
+            return fail("msaa");
+        }
+        if !self.view.position.iter().all(|p| p.is_finite()) {
+            return fail("position");
+        }
+        if !(self.view.zoom.is_finite() && self.view.zoom > 0.0) {
+            return fail("zoom");
+        }
+        if !self.rotation.is_finite() {
+            return fail("rotation");
+        }
+        for layer in &self.layers {
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return fail("opacity");
+            }
+            if !layer.param.is_finite() {
+                return fail("param");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Preset;
+
+    fn test_scene() -> SavedScene {
+        SavedScene {
+            fractal: Fractals::BurningShip,
+            num_iters: 12345,
+            smooth_enabled: true,
+            msaa: 4,
+            view: View {
+                position: [0.7436438870371587, -0.131825904205312],
+                zoom: 123456.79,
+            },
+            rotation: -33.3,
+            layers: vec![
+                Layer::default(),
+                Layer {
+                    algorithm: Algorithm::StripeAverage,
+                    transfer: Transfer::Log,
+                    blend: BlendMode::Overlay,
+                    opacity: 0.35,
+                    param: 7.5,
+                    num_colors: 42,
+                    palette: Preset::Fire.palette(),
+                    palette_mode: PaletteMode::Cosine,
+                    cosine: CosinePalette::random(7),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let scene = test_scene();
+        assert_eq!(SavedScene::parse(&scene.serialize()).unwrap(), scene);
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let scene = test_scene();
+        let contents = scene
+            .serialize()
+            .replace("\n[layer]", "\n# a comment\n\n[layer]");
+        assert_eq!(SavedScene::parse(&contents).unwrap(), scene);
+    }
+
+    #[test]
+    fn malformed() {
+        let scene = test_scene().serialize();
+        let broken = [
+            String::new(),
+            scene.replace(HEADER, "some other file"),
+            scene.replace("fractal = Burning ship", "fractal = Teapot"),
+            scene.replace("num_iters = 12345", "num_iters = lots"),
+            scene.replace("num_iters = 12345", "num_iters 12345"),
+            scene.replace("msaa = 4", "colour = 4"),
+            scene.replace(
+                "position = 0.7436438870371587 -0.131825904205312",
+                "position = 0.5",
+            ),
+            scene.replace("opacity = 0.35", "opacity = 0.3.5"),
+            scene.replacen("stop = 0 ", "stop = 0 #nothex", 1),
+            scene.replace("cosine = ", "cosine = 1 2 3 #"),
+            format!("{HEADER}\nfractal = Mandelbrot\n"),
+            format!("{HEADER}\n[layer]\nalgorithm = Escape time\n"),
+        ];
+        for contents in broken {
+            assert!(SavedScene::parse(&contents).is_err(), "{contents}");
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let scene = test_scene().serialize();
+        let broken = [
+            scene.replace("num_iters = 12345", "num_iters = 0"),
+            scene.replace("num_iters = 12345", "num_iters = 4000000000"),
+            scene.replace("msaa = 4", "msaa = 0"),
+            scene.replace("msaa = 4", "msaa = 100000"),
+            scene.replace("zoom = 123456.79", "zoom = 0"),
+            scene.replace("zoom = 123456.79", "zoom = -5"),
+            scene.replace("zoom = 123456.79", "zoom = inf"),
+            scene.replace("position = 0.7436438870371587", "position = NaN"),
+            scene.replace("rotation = -33.3", "rotation = NaN"),
+            scene.replace("opacity = 0.35", "opacity = 1.5"),
+            scene.replace("opacity = 0.35", "opacity = -1"),
+        ];
+        for contents in broken {
+            assert!(SavedScene::parse(&contents).is_err(), "{contents}");
+        }
+    }
+
+    #[test]
+    fn stops_get_normalized() {
+        let contents = format!(
+            "{HEADER}\n[layer]\nstop = 0.75 #0000ff\nstop = -2 #ff0000\nstop = 0.5 #00ff00\n"
+        );
+        let palette = &SavedScene::parse(&contents).unwrap().layers[0].palette;
+        assert_eq!(palette.positions, vec![0.0, 0.5, 0.75]);
+        assert_eq!(
+            palette.colors.iter().map(color_hex).collect::<Vec<_>>(),
+            vec!["#ff0000ff", "#00ff00ff", "#0000ffff"]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+//What screenshots get saved as. Everything but the plain png gets rendered into a float texture
+//so gradients don't band
+#[derive(Default, Clone, Debug, PartialEq, Eq, Copy)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Png16,
+    //Linear 32 bit floats, nothing clamped, for compositing
+    Exr,
+    Tiff16,
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Png => write!(f, "PNG"),
+            Self::Png16 => write!(f, "PNG 16 bit"),
+            Self::Exr => write!(f, "OpenEXR float"),
+            Self::Tiff16 => write!(f, "TIFF 16 bit"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 4] = [Self::Png, Self::Png16, Self::Exr, Self::Tiff16];
+
+    pub fn is_float(self) -> bool {
+        self != Self::Png
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png | Self::Png16 => "png",
+            Self::Exr => "exr",
+            Self::Tiff16 => "tiff",
+        }
+    }
+}
+
+//Named after the current time so they never overwrite each other
+fn new_path(format: ExportFormat) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(SCREENSHOT_DIR)?;
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    Ok(PathBuf::from(format!(
+        "{SCREENSHOT_DIR}/fractal_{time}.{}",
+        format.extension()
+    )))
+}
+
+//Saves RGBA pixels as a png
+pub fn save(pixels: Vec<u8>, width: u32, height: u32) -> Result<PathBuf, image::ImageError> {
+    let path = new_path(ExportFormat::Png)?;
+    let image = image::RgbaImage::from_raw(width, height, pixels)
+        .expect("Screenshot buffer should match its size");
+    image.save(&path)?;
+    Ok(path)
+}
+
+//Same as in srgb.wgsl
+fn linear_to_srgb(x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+//Saves linear float RGBA pixels, exr keeps them as they are and the 16 bit formats get them
+//encoded as sRGB like any other image
+pub fn save_float(
+    pixels: Vec<f32>,
+    width: u32,
+    height: u32,
+    format: ExportFormat,
+) -> Result<PathBuf, image::ImageError> {
+    let path = new_path(format)?;
+    if format == ExportFormat::Exr {
+        image::Rgba32FImage::from_raw(width, height, pixels)
+            .expect("Screenshot buffer should match its size")
+            .save(&path)?;
+    } else {
+        let pixels = pixels
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let [r, g, b] = [p[0], p[1], p[2]].map(linear_to_srgb);
+                [r, g, b, p[3].clamp(0.0, 1.0)].map(|x| (x * 65535.0).round() as u16)
+            })
+            .collect();
+        image::ImageBuffer::<image::Rgba<u16>, Vec<u16>>::from_raw(width, height, pixels)
+            .expect("Screenshot buffer should match its size")
+            .save(&path)?;
+    }
+    Ok(path)
+}
@@ -0,0 +1,46 @@
+struct BlitUniforms {
+  //Part of the texture that has the image in it
+  scale: vec2<f32>,
+  //Where on the image each point of the screen is, in fractions of the screen around the
+  //middle, for showing an old image while the view moves
+  offset: vec2<f32>,
+  transform: mat2x2<f32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> uniforms: BlitUniforms;
+
+@group(0)
+@binding(1)
+var image: texture_2d<f32>;
+
+@group(0)
+@binding(2)
+var image_sampler: sampler;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+}
+
+//One big triangle that covers the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uniforms.transform * (uv - 0.5) + 0.5 + uniforms.offset;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(image, image_sampler, in.uv * uniforms.scale);
+    //Nothing has been rendered there yet
+    if any(in.uv < vec2<f32>(0.0)) || any(in.uv > vec2<f32>(1.0)) {
+        discard;
+    }
+    //The weights are in alpha
+    return encode_output(vec4<f32>(clamp(color.rgb / max(color.a, 0.0001), vec3<f32>(0.0), vec3<f32>(1.0)), 1.0));
+}
@@ -0,0 +1,37 @@
+//Goes after fractal.wgsl, colors what the compute shader left in the iteration cache
+
+@group(1)
+@binding(0)
+var cache_a: texture_2d<u32>;
+
+@group(1)
+@binding(1)
+var cache_b: texture_2d<u32>;
+
+@group(1)
+@binding(2)
+var<uniform> cache: CacheUniforms;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0)
+  uv: vec2<f32>,
+}
+
+fn cached_color(position: vec4<f32>) -> vec4<f32> {
+    let pixel = cache_slot(vec2<u32>(position.xy), cache);
+    return get_color(unpack_orbit(textureLoad(cache_a, pixel, 0), textureLoad(cache_b, pixel, 0)));
+}
+
+//Into the accumulation texture, weighted by the filter like frag.wgsl does
+@fragment
+fn main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let weight = pixel_sample(vec2<u32>(in.position.xy), uniforms.sample).z;
+    return vec4<f32>(cached_color(in.position).rgb * weight, weight);
+}
+
+//Straight onto the screen
+@fragment
+fn show(in: VertexOutput) -> @location(0) vec4<f32> {
+    return encode_output(cached_color(in.position));
+}
@@ -0,0 +1,53 @@
+//Goes after fractal.wgsl, iterates one sample per pixel into the iteration cache
+
+@group(1)
+@binding(0)
+var cache_a: texture_storage_2d<rgba32uint, write>;
+
+@group(1)
+@binding(1)
+var cache_b: texture_storage_2d<rgba32uint, write>;
+
+@group(1)
+@binding(2)
+var<uniform> cache: CacheUniforms;
+
+struct IterationStats {
+  pixels: atomic<u32>,
+  limited: atomic<u32>,
+}
+
+@group(1)
+@binding(3)
+var<storage, read_write> stats: IterationStats;
+
+//Side of a tile in pixels, same as in tiles.rs. The workgroups in z are the tiles
+let TILE_SIZE: u32 = 64u;
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    //Tiles go left to right and then top to bottom
+    let columns = (uniforms.resolution.x + TILE_SIZE - 1u) / TILE_SIZE;
+    let tile = cache.first_tile + id.z;
+    let pixel = vec2<u32>(tile % columns, tile / columns) * TILE_SIZE + id.xy;
+    if pixel.x >= uniforms.resolution.x || pixel.y >= uniforms.resolution.y {
+        return;
+    }
+    //Only the pixels that just came onto the screen need iterating
+    let old = vec2<i32>(pixel) + cache.shift;
+    if cache.reuse != 0u && all(old >= vec2<i32>(0)) && all(old < vec2<i32>(uniforms.resolution)) {
+        return;
+    }
+    //Same thing the vertex shader hands to the fragment shader for the middle of this pixel
+    let half = vec2<f32>(uniforms.resolution) * 0.5;
+    let uv = vec2<f32>(f32(pixel.x) + 0.5 - half.x, half.y - f32(pixel.y) - 0.5);
+    let orbit = fractal(screen_to_plane(uv, pixel_sample(pixel, uniforms.sample).xy));
+    atomicAdd(&stats.pixels, 1u);
+    if orbit.limited {
+        atomicAdd(&stats.limited, 1u);
+    }
+    let slot = cache_slot(pixel, cache);
+    textureStore(cache_a, slot, pack_orbit_a(orbit));
+    textureStore(cache_b, slot, pack_orbit_b(orbit));
+}
@@ -0,0 +1,612 @@
+//Everything the fractal shaders share, frag.wgsl, color.wgsl and compute.wgsl get appended to
+//this for their entry points. srgb.wgsl goes in front, and colors are all linear in here
+
+struct ShaderDataUniforms {
+  position: vec2<f32>,
+  resolution: vec2<u32>,
+  aspect: f32,
+  //In radians
+  rotation: f32,
+  zoom: f32,
+  num_layers: u32,
+  fractal: u32,
+  max_iter: u32,
+  msaa: u32,
+  //Index of the first sample
+  sample: u32,
+  //How close z has to come back to itself to count as a cycle, 0 turns period checking off
+  period_tolerance: f32,
+  //Where the samples go inside a pixel, the top bit moves them around differently per pixel
+  aa_pattern: u32,
+  //How much each sample counts depending on how far it is from the middle of the pixel
+  aa_filter: u32,
+  //How many samples the whole picture gets, msaa is only the ones in this pass
+  aa_samples: u32,
+  //How much the colors around a pixel have to differ for it to get more samples, 0 gives
+  //every pixel all of them
+  adaptive_threshold: f32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> uniforms : ShaderDataUniforms;
+
+struct ColorStop {
+  color: vec4<f32>,
+  //Position inside one repetition of the palette
+  position: f32,
+}
+
+@group(0)
+@binding(1)
+var<storage, read>  colors : array<ColorStop>;
+
+struct Layer {
+  algorithm: u32,
+  transfer: u32,
+  blend: u32,
+  opacity: f32,
+  palette_mode: u32,
+  color_num: u32,
+  //Where the colors of this layer start in the color array
+  stop_offset: i32,
+  stop_count: i32,
+  //Stripe frequency for the stripe average
+  param: f32,
+  //a + b * cos(2π(c * t + d)), only xyz are used
+  cosine_a: vec4<f32>,
+  cosine_b: vec4<f32>,
+  cosine_c: vec4<f32>,
+  cosine_d: vec4<f32>,
+}
+
+@group(0)
+@binding(2)
+var<storage, read>  layers : array<Layer>;
+
+//Everything the coloring layers need to know about an orbit
+struct OrbitData {
+  iter: f32,
+  inside: bool,
+  //Distance to the set in fractal space
+  distance: f32,
+  //Closest the orbit got to the origin
+  trap: f32,
+  //Results of the averaging algorithms, one per layer
+  averages: array<f32, 8>,
+  //Normal of the set for lighting
+  normal: vec2<f32>,
+  //Length of the cycle an inside orbit fell into, 0 if it wasn't found
+  period: f32,
+  //Ran out of iterations without escaping or finding a cycle, auto iterations counts these
+  limited: bool,
+}
+
+//I don't remember where I got this, but it should work
+fn rand(s: f32) -> f32 {
+    return fract(sin(s * 12.9898) * 43758.5453);
+}
+
+fn complex_square(z: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y);
+}
+fn complex_cube(z: vec2<f32>) -> vec2<f32> {
+    let x2 = z.x * z.x;
+    let y2 = z.y * z.y;
+    return vec2<f32>(z.x * x2 - 3.0 * z.x * y2, 3.0 * x2 * z.y - z.y * y2);
+}
+fn complex_pow(z: vec2<f32>, n: f32) -> vec2<f32> {
+    let r = length(z);
+    let theta = atan2(z.y, z.x);
+    return pow(r, n) * vec2(cos(n * theta), sin(n * theta));
+}
+fn complex_mul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+fn complex_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let denumenator = 1.0 / (b.x * b.x + b.y * b.y);
+    //Multiplying should be a bit faster
+    return vec2<f32>((a.x * b.x + a.y * b.y) * denumenator, (a.y * b.x - a.x * b.y) * denumenator);
+}
+fn complex_sqrt(z: vec2<f32>) -> vec2<f32> {
+    let l = length(z) * 0.5;
+    let x = z.x * .5;
+    //I don't think I need this much optimiazation, but better safe than sorry
+    return vec2(sqrt(l + x), sign(z.y) * sqrt(l - x));
+} 
+
+fn mandelbrot(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let c2 = dot(c, c);
+
+    // skip computation inside M1 - https://iquilezles.org/articles/mset1bulb
+    if 256.0 * c2 * c2 - 96.0 * c2 + 32.0 * c.x - 3.0 < 0.0 {
+        return vec2<f32>(69.0, 4200.0);
+    }
+    // skip computation inside M2 - https://iquilezles.org/articles/mset2bulb
+    if 16.0 * (c2 + 2.0 * c.x + 1.0) - 1.0 < 0.0 {
+        return vec2<f32>(69.0, 4200.0);
+    }
+    return complex_square(z) + c;
+}
+fn burning_ship(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let z1 = abs(z);
+    return complex_square(z1) + c;
+}
+fn tricorn(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let z1 = z * vec2<f32>(1.0, -1.0);
+    return complex_square(z1) + c;
+}
+fn feather(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    if length(c) < 0.53 {
+        return vec2<f32>(69.0, 4200.0);
+    }
+    return complex_div(complex_cube(z), (vec2<f32>(1.0, 0.0) + (z * z))) + c;
+}
+fn eye(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    if c.x < -1.34 || c.x > 4.0 || abs(c.y) > 1.65 {
+        return vec2<f32>(69.0, 4200.0);
+    }
+    return complex_square(complex_div(z, c)) + c;
+}
+
+fn get_col(coord: f32, layer: Layer) -> vec4<f32> {
+    let col_num = i32(layer.color_num);
+    let offset = layer.stop_offset;
+    let len = layer.stop_count;
+    if col_num == 1 {
+        return colors[offset].color;
+    }
+    //Every col_num - 1 bands make up a full gradient, the palette repeats every stop_count bands
+    let t = fract(coord * f32(col_num - 1) / f32(len));
+
+    var prev = len - 1;
+    for (var i = 0; i < len; i += 1) {
+        if colors[offset + i].position <= t {
+            prev = i;
+        }
+    }
+    let next = (prev + 1) % len;
+    var start = colors[offset + prev].position;
+    var end = colors[offset + next].position;
+    //Wrapping around the end of the palette
+    if start > t {
+        start -= 1.0;
+    }
+    if end <= t {
+        end += 1.0;
+    }
+    return mix(colors[offset + prev].color, colors[offset + next].color, (t - start) / max(end - start, 0.00001));
+}
+
+//How many bands one repetition of a cosine palette spans
+let COSINE_BANDS: f32 = 10.0;
+
+fn get_cosine_col(coord: f32, layer: Layer) -> vec4<f32> {
+    let t = coord * f32(i32(layer.color_num) - 1) / COSINE_BANDS;
+    let col = layer.cosine_a.xyz + layer.cosine_b.xyz * cos(6.28318530718 * (layer.cosine_c.xyz * t + layer.cosine_d.xyz));
+    //The formula is meant for sRGB, everything else works in linear
+    return vec4<f32>(srgb_to_linear(clamp(col, vec3<f32>(0.0), vec3<f32>(1.0))), 1.0);
+}
+
+//Maps [0, inf) to [0, 1)
+fn squash(x: f32) -> f32 {
+    return x / (1.0 + x);
+}
+
+fn transfer(x: f32, kind: u32) -> f32 {
+    if kind == 1u {
+        return sqrt(x);
+    } else if kind == 2u {
+        return pow(x, 1.0 / 3.0);
+    } else if kind == 3u {
+        return log(1.0 + x);
+    } else if kind == 4u {
+        return x * x;
+    }
+    return x;
+}
+
+//Stripe, triangle inequality and curvature averages
+fn is_averaging(algorithm: u32) -> bool {
+    return algorithm == 3u || algorithm == 5u || algorithm == 6u;
+}
+
+//Value of a coloring algorithm, along with the largest value it can have
+fn layer_value(orbit: OrbitData, layer: u32) -> vec2<f32> {
+    let algorithm = layers[layer].algorithm;
+    if algorithm == 1u {
+        //Distance in pixels
+        return vec2<f32>(squash(orbit.distance * uniforms.zoom), 1.0);
+    } else if algorithm == 2u {
+        return vec2<f32>(squash(orbit.trap), 1.0);
+    } else if is_averaging(algorithm) {
+        //Arrays passed by value can't be indexed dynamically
+        var averages = orbit.averages;
+        return vec2<f32>(averages[layer], 1.0);
+    } else if algorithm == 4u {
+        //Light coming from the top right at a 45 degree angle, https://www.math.univ-toulouse.fr/~cheritat/wiki-draw/index.php/Mandelbrot_set#Normal_map_effect
+        let height = 1.5;
+        let light = dot(orbit.normal, vec2<f32>(0.70710678)) + height;
+        return vec2<f32>(clamp(light / (1.0 + height), 0.0, 1.0), 1.0);
+    } else if algorithm == 7u {
+        //Every period gets its own band of the palette
+        return vec2<f32>(orbit.period, 1.0);
+    }
+    return vec2<f32>(orbit.iter, f32(uniforms.max_iter));
+}
+
+fn blend(base: vec3<f32>, top: vec3<f32>, mode: u32) -> vec3<f32> {
+    if mode == 1u {
+        return base * top;
+    } else if mode == 2u {
+        return 1.0 - (1.0 - base) * (1.0 - top);
+    } else if mode == 3u {
+        return select(1.0 - 2.0 * (1.0 - base) * (1.0 - top), 2.0 * base * top, base < vec3<f32>(0.5));
+    } else if mode == 4u {
+        return min(base + top, vec3<f32>(1.0));
+    }
+    return top;
+}
+
+fn get_color(orbit: OrbitData) -> vec4<f32> {
+    if orbit.inside && orbit.period == 0.0 {
+        return vec4<f32>(0.0);
+    }
+    var col = vec3<f32>(0.0);
+    var colored = false;
+    for (var i = 0u; i < uniforms.num_layers; i += 1u) {
+        let layer = layers[i];
+        //The inside only gets the period layers, the outside everything else
+        if orbit.inside != (layer.algorithm == 7u) {
+            continue;
+        }
+        colored = true;
+        let value = layer_value(orbit, i);
+        let coord = transfer(max(value.x, 0.0), layer.transfer) / transfer(value.y, layer.transfer);
+        var layer_col: vec4<f32>;
+        if layer.palette_mode == 1u {
+            layer_col = get_cosine_col(coord, layer);
+        } else {
+            layer_col = get_col(coord, layer);
+        }
+        col = mix(col, blend(col, layer_col.rgb, layer.blend), layer.opacity * layer_col.a);
+    }
+    //Nothing to color it with, so it stays see through like the rest of the inside
+    if !colored {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(col, 1.0);
+}
+
+fn iterate(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    if (uniforms.fractal & 1u) == 1u {
+        return mandelbrot(z, c);
+    } else if (uniforms.fractal & 2u) == 2u {
+        return burning_ship(z, c);
+    } else if (uniforms.fractal & 4u) == 4u {
+        return tricorn(z, c);
+    } else if (uniforms.fractal & 8u) == 8u {
+        return feather(z, c);
+    }
+    return eye(z, c);
+}
+
+//Derivative of the next z with respect to c, used for distance estimation and lighting
+fn derivative(z: vec2<f32>, dz: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
+    let one = vec2<f32>(1.0, 0.0);
+    if (uniforms.fractal & 2u) == 2u {
+        //abs() folds the derivative the same way it folds z
+        return 2.0 * complex_mul(abs(z), dz * sign(z)) + one;
+    } else if (uniforms.fractal & 4u) == 4u {
+        let conj = vec2<f32>(1.0, -1.0);
+        return 2.0 * complex_mul(z * conj, dz * conj) + one;
+    } else if (uniforms.fractal & 8u) == 8u {
+        //d/dz z^3 / (1 + z^2) = (z^4 + 3z^2) / (1 + z^2)^2
+        let z2 = complex_square(z);
+        let denominator = complex_square(one + z2);
+        return complex_mul(complex_div(complex_square(z2) + 3.0 * z2, denominator), dz) + one;
+    } else if (uniforms.fractal & 16u) == 16u {
+        //(z / c)^2 + c depends on c directly too
+        let c2 = complex_square(c);
+        let dfdz = complex_div(2.0 * z, c2);
+        let dfdc = one - complex_div(2.0 * complex_square(z), complex_mul(c2, c));
+        return complex_mul(dfdz, dz) + dfdc;
+    }
+    return 2.0 * complex_mul(z, dz) + one;
+}
+
+//The value an averaging algorithm adds for a single iteration, z1 is the newest z
+fn average_term(algorithm: u32, param: f32, z1: vec2<f32>, z2: vec2<f32>, z3: vec2<f32>, c: vec2<f32>) -> f32 {
+    if algorithm == 5u {
+        //|z1 - c| is |z2^2| for the Mandelbrot set, so the bounds work for the other formulas too
+        let zc = length(z1 - c);
+        let lower = abs(zc - length(c));
+        let upper = zc + length(c);
+        return (length(z1) - lower) / max(upper - lower, 1e-20);
+    } else if algorithm == 6u {
+        let a = z1 - z2;
+        let b = z2 - z3;
+        if dot(b, b) == 0.0 {
+            return 0.0;
+        }
+        let r = complex_div(a, b);
+        return abs(atan2(r.y, r.x)) / 3.14159265359;
+    }
+    return 0.5 * sin(param * atan2(z1.y, z1.x)) + 0.5;
+}
+
+//Escape time is the only algorithm that works fine with a tiny bailout radius, the period only
+//matters for the inside
+fn needs_orbit_data() -> bool {
+    for (var i = 0u; i < uniforms.num_layers; i += 1u) {
+        if layers[i].algorithm != 0u && layers[i].algorithm != 7u {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn fractal(C: vec2<f32>) -> OrbitData {
+    var coords = vec2<f32>(0.0);
+    var dz = vec2<f32>(0.0);
+    var iter = 0u;
+    var trap = 1e20;
+    //The two previous z values, for the curvature average
+    var z2 = vec2<f32>(0.0);
+    var z3 = vec2<f32>(0.0);
+    //Per layer sum, last added value and number of values of the averaging algorithms
+    var sums: array<vec3<f32>, 8>;
+
+    //Brent's cycle detection, z gets compared to a saved z which jumps ahead after a power of
+    //two iterations, so a cycle of any length gets caught soon after the orbit settles into it
+    var saved = vec2<f32>(0.0);
+    var steps = 0u;
+    var next_save = 1u;
+    var period = 0u;
+    let tolerance = uniforms.period_tolerance * uniforms.period_tolerance;
+
+    var max_dot = 5.0;
+    if (uniforms.fractal & 8u) == 8u || (uniforms.fractal & 16u) == 16u {max_dot = 200000.0;}
+    if needs_orbit_data() {max_dot = max(max_dot, 1000.0);}
+    let max_iteration = uniforms.max_iter;
+
+    while dot(coords, coords) <= max_dot && iter < max_iteration {
+        dz = derivative(coords, dz, C);
+        z3 = z2;
+        z2 = coords;
+        coords = iterate(coords, C);
+        trap = min(trap, length(coords));
+        //The first z is just c, so it doesn't say anything about the orbit
+        if iter > 0u {
+            for (var l = 0u; l < uniforms.num_layers; l += 1u) {
+                let algorithm = layers[l].algorithm;
+                //Curvature needs 3 points
+                if is_averaging(algorithm) && (algorithm != 6u || iter > 1u) {
+                    let term = average_term(algorithm, layers[l].param, coords, z2, z3, C);
+                    sums[l] = vec3<f32>(sums[l].x + term, term, sums[l].z + 1.0);
+                }
+            }
+        }
+        iter += 1u;
+        if tolerance > 0.0 {
+            steps += 1u;
+            let d = coords - saved;
+            if dot(d, d) < tolerance {
+                period = steps;
+                break;
+            }
+            if steps == next_save {
+                saved = coords;
+                next_save *= 2u;
+                steps = 0u;
+            }
+        }
+    }
+    var out: OrbitData;
+    var i = f32(iter);
+    let skipped = coords.x == 69.0 && coords.y == 4200.0;
+    out.limited = iter >= max_iteration && period == 0u && !skipped;
+    if skipped || period > 0u {
+        i = f32(max_iteration);
+    } else if (uniforms.fractal & 2147483648u) != 0u {
+        i = i - log2(log2(dot(coords, coords))) + 4.0;
+    }
+    out.iter = i;
+    out.inside = i >= f32(max_iteration);
+    let r = length(coords);
+    out.distance = 0.5 * r * log(r) / max(length(dz), 1e-20);
+    out.trap = trap;
+    out.normal = normalize(complex_div(coords, dz));
+    out.period = f32(period);
+
+    //Fractional part of the smooth iteration count, used to blend between the average with
+    //and without the last iteration, so the averages don't have bands
+    let fraction = clamp(1.0 + log2(log(max_dot) / (2.0 * log(r))), 0.0, 1.0);
+    for (var l = 0u; l < uniforms.num_layers; l += 1u) {
+        let sum = sums[l];
+        let average = sum.x / max(sum.z, 1.0);
+        let previous = (sum.x - sum.y) / max(sum.z - 1.0, 1.0);
+        out.averages[l] = mix(previous, average, fraction);
+    }
+    return out;
+}
+
+//Maps a point on the screen, relative to the middle with y pointing up, onto the plane. Each
+//sample gets jittered by a different amount inside the pixel
+//Integer hash from https://www.pcg-random.org, sin based ones fall apart for big inputs
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+//A random point in the unit square that stays the same for a pixel
+fn pixel_hash(pixel: vec2<u32>) -> vec2<f32> {
+    let a = pcg(pixel.x + pcg(pixel.y));
+    let b = pcg(a);
+    return fract(vec2<f32>(f32(a), f32(b)) / 4294967296.0);
+}
+
+fn halton(index: u32, base: u32) -> f32 {
+    var fraction = 1.0;
+    var result = 0.0;
+    var n = index;
+    loop {
+        if n == 0u {
+            break;
+        }
+        fraction = fraction / f32(base);
+        result += fraction * f32(n % base);
+        n = n / base;
+    }
+    return result;
+}
+
+//Where sample `index` out of `total` goes in the unit square
+fn sample_point(index: u32, total: u32) -> vec2<f32> {
+    let pattern = uniforms.aa_pattern & 255u;
+    let side = u32(ceil(sqrt(f32(total))));
+    let cell = vec2<f32>(f32(index % side), f32((index / side) % side));
+    //Stratified, one jittered sample in each cell of a grid
+    if pattern == 1u {
+        return (cell + vec2<f32>(rand(f32(index) * .54321), rand(f32(index) * .12345))) / f32(side);
+    }
+    //Rotated grid, turned by atan(1/2) so no two samples share a row or a column
+    if pattern == 2u {
+        let angle = 0.4636476;
+        let rotation = mat2x2<f32>(cos(angle), sin(angle), -sin(angle), cos(angle));
+        return fract(rotation * ((cell + 0.5) / f32(side) - 0.5) + 0.5);
+    }
+    if pattern == 3u {
+        return vec2<f32>(halton(index + 1u, 2u), halton(index + 1u, 3u));
+    }
+    //R2 from http://extremelearning.com.au/unreasonable-effectiveness-of-quasirandom-sequences
+    if pattern == 4u {
+        let g = 1.32471795724;
+        return fract(vec2<f32>(0.5) + f32(index + 1u) * vec2<f32>(1.0 / g, 1.0 / (g * g)));
+    }
+    return vec2<f32>(rand(f32(index) * .54321), rand(f32(index) * .12345));
+}
+
+//How far from the middle of the pixel the filter reaches, in pixels
+fn filter_radius() -> f32 {
+    switch uniforms.aa_filter {
+        case 1u: {
+            return 1.0;
+        }
+        case 2u: {
+            return 1.5;
+        }
+        case 3u: {
+            return 2.0;
+        }
+        default: {
+            return 0.5;
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if abs(x) < 0.0001 {
+        return 1.0;
+    }
+    let px = 3.14159265 * x;
+    return sin(px) / px;
+}
+
+fn filter_weight(offset: vec2<f32>) -> f32 {
+    let d = abs(offset);
+    switch uniforms.aa_filter {
+        //Tent
+        case 1u: {
+            return max(1.0 - d.x, 0.0) * max(1.0 - d.y, 0.0);
+        }
+        //Gaussian with a standard deviation of half a pixel
+        case 2u: {
+            return exp(-2.0 * dot(d, d));
+        }
+        //Lanczos with two lobes, goes negative so it sharpens
+        case 3u: {
+            return sinc(d.x) * sinc(d.x * 0.5) * sinc(d.y) * sinc(d.y * 0.5);
+        }
+        default: {
+            return 1.0;
+        }
+    }
+}
+
+//Where a sample goes relative to the middle of its pixel, in pixels, and in z how much it counts
+fn pixel_sample(pixel: vec2<u32>, index: u32) -> vec3<f32> {
+    let total = uniforms.aa_samples;
+    if total <= 1u {
+        return vec3<f32>(0.0, 0.0, 1.0);
+    }
+    var spot = sample_point(index, total);
+    //Cranley-Patterson rotation, neighbouring pixels don't line their samples up
+    if (uniforms.aa_pattern & 2147483648u) != 0u {
+        spot = fract(spot + pixel_hash(pixel));
+    }
+    let offset = (spot - 0.5) * 2.0 * filter_radius();
+    return vec3<f32>(offset, filter_weight(offset));
+}
+
+//Takes the offset from the middle of the pixel in pixels
+fn screen_to_plane(uv: vec2<f32>, offset: vec2<f32>) -> vec2<f32> {
+    //Rotates the screen around its center before mapping it onto the plane
+    let rotation = mat2x2<f32>(
+        cos(uniforms.rotation), sin(uniforms.rotation),
+        -sin(uniforms.rotation), cos(uniforms.rotation)
+    );
+    return (rotation * ((uv + offset) * vec2<f32>(1.0, -1.0)) / uniforms.zoom) - uniforms.position;
+}
+
+//The iteration cache keeps an orbit's data in two texels so it can be colored again without
+//iterating. The averages only need half precision, and inside points have no distance so the
+//period goes there
+fn pack_orbit_a(orbit: OrbitData) -> vec4<u32> {
+    return vec4<u32>(
+        bitcast<u32>(orbit.iter),
+        bitcast<u32>(select(orbit.distance, orbit.period, orbit.inside)),
+        bitcast<u32>(orbit.trap),
+        pack2x16snorm(orbit.normal),
+    );
+}
+fn pack_orbit_b(orbit: OrbitData) -> vec4<u32> {
+    var averages = orbit.averages;
+    return vec4<u32>(
+        pack2x16float(vec2<f32>(averages[0], averages[1])),
+        pack2x16float(vec2<f32>(averages[2], averages[3])),
+        pack2x16float(vec2<f32>(averages[4], averages[5])),
+        pack2x16float(vec2<f32>(averages[6], averages[7])),
+    );
+}
+fn unpack_orbit(a: vec4<u32>, b: vec4<u32>) -> OrbitData {
+    var out: OrbitData;
+    out.iter = bitcast<f32>(a.x);
+    out.inside = out.iter >= f32(uniforms.max_iter);
+    out.distance = select(bitcast<f32>(a.y), 0.0, out.inside);
+    out.period = select(0.0, bitcast<f32>(a.y), out.inside);
+    out.trap = bitcast<f32>(a.z);
+    out.normal = unpack2x16snorm(a.w);
+    let a01 = unpack2x16float(b.x);
+    let a23 = unpack2x16float(b.y);
+    let a45 = unpack2x16float(b.z);
+    let a67 = unpack2x16float(b.w);
+    out.averages = array<f32, 8>(a01.x, a01.y, a23.x, a23.y, a45.x, a45.y, a67.x, a67.y);
+    return out;
+}
+
+//Where the screen is in the iteration cache. It wraps around, so panning only moves the origin
+struct CacheUniforms {
+  origin: vec2<u32>,
+  //Where a pixel was on the screen before the last pan, relative to where it is now
+  shift: vec2<i32>,
+  //Whether the pixels that were already on the screen got kept
+  reuse: u32,
+  //The compute shader does a few tiles at a time, starting from this one
+  first_tile: u32,
+}
+
+fn cache_slot(pixel: vec2<u32>, cache: CacheUniforms) -> vec2<i32> {
+    return vec2<i32>((pixel + cache.origin) % uniforms.resolution);
+}
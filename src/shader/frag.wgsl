@@ -1,22 +1,17 @@
-struct ShaderDataUniforms {
-  position: vec2<f32>,
-  resolution: vec2<u32>,
-  aspect: vec2<f32>,
-  zoom: f32,
-  arr_len: i32,
-  fractal: u32,
-  max_iter: u32,
-  color_num: u32,
-  msaa: u32,
-}
+//Goes after fractal.wgsl, computes and colors every sample of a pixel in one go
 
-@group(0)
+//Only the later progressive samples look in here, to find out where the edges are
+@group(1)
 @binding(0)
-var<uniform> uniforms : ShaderDataUniforms;
+var cache_a: texture_2d<u32>;
 
-@group(0)
+@group(1)
 @binding(1)
-var<storage, read>  colors : array<vec4<f32>>;
+var cache_b: texture_2d<u32>;
+
+@group(1)
+@binding(2)
+var<uniform> cache: CacheUniforms;
 
 struct VertexOutput {
   @builtin(position) position: vec4<f32>,
@@ -24,135 +19,107 @@ struct VertexOutput {
   uv: vec2<f32>,
 }
 
-//I don't remember where I got this, but it should work
-fn rand(s: f32) -> f32 {
-    return fract(sin(s * 12.9898) * 43758.5453);
+struct Samples {
+  //Colors times their filter weights, and the weights in w, both averaged over the samples
+  weighted: vec4<f32>,
+  alpha: f32,
 }
 
-fn complex_square(z: vec2<f32>) -> vec2<f32> {
-    return vec2<f32>(z.x * z.x - z.y * z.y, 2.0 * z.x * z.y);
-}
-fn complex_cube(z: vec2<f32>) -> vec2<f32> {
-    let x2 = z.x * z.x;
-    let y2 = z.y * z.y;
-    return vec2<f32>(z.x * x2 - 3.0 * z.x * y2, 3.0 * x2 * z.y - z.y * y2);
-}
-fn complex_pow(z: vec2<f32>, n: f32) -> vec2<f32> {
-    let r = length(z);
-    let theta = atan2(z.y, z.x);
-    return pow(r, n) * vec2(cos(n * theta), sin(n * theta));
-}
-fn complex_div(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
-    let denumenator = 1.0 / (b.x * b.x + b.y * b.y);
-    //Multiplying should be a bit faster
-    return vec2<f32>((a.x * b.x + a.y * b.y) * denumenator, (a.y * b.x - a.x * b.y) * denumenator);
-}
-fn complex_sqrt(z: vec2<f32>) -> vec2<f32> {
-    let l = length(z) * 0.5;
-    let x = z.x * .5;
-    //I don't think I need this much optimiazation, but better safe than sorry
-    return vec2(sqrt(l + x), sign(z.y) * sqrt(l - x));
-} 
-
-fn mandelbrot(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
-    let c2 = dot(c, c);
-
-    // skip computation inside M1 - https://iquilezles.org/articles/mset1bulb
-    if 256.0 * c2 * c2 - 96.0 * c2 + 32.0 * c.x - 3.0 < 0.0 {
-        return vec2<f32>(69.0, 4200.0);
-    }
-    // skip computation inside M2 - https://iquilezles.org/articles/mset2bulb
-    if 16.0 * (c2 + 2.0 * c.x + 1.0) - 1.0 < 0.0 {
-        return vec2<f32>(69.0, 4200.0);
-    }
-    return complex_square(z) + c;
-}
-fn burning_ship(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
-    let z1 = abs(z);
-    return complex_square(z1) + c;
-}
-fn tricorn(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
-    let z1 = z * vec2<f32>(1.0, -1.0);
-    return complex_square(z1) + c;
-}
-fn feather(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
-    if length(c) < 0.53 {
-        return vec2<f32>(69.0, 4200.0);
-    }
-    return complex_div(complex_cube(z), (vec2<f32>(1.0, 0.0) + (z * z))) + c;
-}
-fn eye(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {
-    if c.x < -1.34 || c.x > 4.0 || abs(c.y) > 1.65 {
-        return vec2<f32>(69.0, 4200.0);
-    }
-    return complex_square(complex_div(z, c)) + c;
+//Adaptive supersampling gets this many samples before it can decide a pixel is done
+let MIN_ADAPTIVE_SAMPLES: u32 = 4u;
+
+fn brightness(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
 }
 
-fn get_col(coord: f32, col_num: i32) -> vec4<f32> {
-    if col_num == 1 {
-        return colors[0];
-    }
-    let cstep1 = 1.0 / f32(col_num - 1);
-    for (var i = 1; i < col_num; i += 1) {
-        if coord < cstep1 * f32(i) {
-            return mix(colors[(i - 1) % uniforms.arr_len], colors[i % uniforms.arr_len], coord / cstep1 - f32(i - 1));
+fn render_samples(in: VertexOutput) -> Samples {
+    let uv = in.uv - vec2<f32>(uniforms.resolution) * 0.5;
+    let pixel = vec2<u32>(in.position.xy);
+
+    var out: Samples;
+    out.weighted = vec4<f32>(0.0);
+    out.alpha = 0.0;
+    var sum = 0.0;
+    var sum_squared = 0.0;
+    var taken = 0u;
+
+    for (var i = 0u; i < uniforms.msaa; i += 1u) {
+        let sample = pixel_sample(pixel, uniforms.sample + i);
+        let color = get_color(fractal(screen_to_plane(uv, sample.xy)));
+        out.weighted += vec4<f32>(color.rgb * sample.z, sample.z);
+        out.alpha += color.a;
+        taken += 1u;
+
+        //Stops early where the samples all came out about the same
+        let b = brightness(color.rgb);
+        sum += b;
+        sum_squared += b * b;
+        if uniforms.adaptive_threshold > 0.0 && taken >= MIN_ADAPTIVE_SAMPLES {
+            let mean = sum / f32(taken);
+            if sum_squared / f32(taken) - mean * mean < uniforms.adaptive_threshold * uniforms.adaptive_threshold {
+                break;
+            }
         }
     }
-    return vec4<f32>(coord);
-}
 
-fn get_color(uv: vec2<f32>, i: f32, max_i: u32) -> vec4<f32> {
-    if i >= f32(max_i) {
-        return vec4<f32>(0.0);
-    }
-    return get_col(f32(i) / f32(max_i), i32(uniforms.color_num));
+    out.weighted /= f32(max(taken, 1u));
+    out.alpha /= f32(max(taken, 1u));
+    return out;
 }
 
-fn fractal(C: vec2<f32>) -> vec4<f32> {
-    var coords = vec2<f32>(0.0);
-    var iter = 0u;
-
-    var max_dot = 5.0;
-    if (uniforms.fractal & 8u) == 8u || (uniforms.fractal & 16u) == 16u {max_dot = 200000.0;}
-    let max_iteration = uniforms.max_iter;
-
-    while dot(coords, coords) <= max_dot && iter < max_iteration {
-
-        if (uniforms.fractal & 1u) == 1u {
-            coords = mandelbrot(coords, C);
-        } else if (uniforms.fractal & 2u) == 2u {
-            coords = burning_ship(coords, C);
-        } else if (uniforms.fractal & 4u) == 4u {
-            coords = tricorn(coords, C);
-        } else if (uniforms.fractal & 8u) == 8u {
-            coords = feather(coords, C);
-        } else if (uniforms.fractal & 16u) == 16u {
-            coords = eye(coords, C);
-        }
-        iter += 1u;
-    }
-    var i = f32(iter);
-    if coords.x == 69.0 && coords.y == 4200.0 {
-        i = f32(max_iteration);
-    } else if (uniforms.fractal & 2147483648u) != 0u {
-        i = i - log2(log2(dot(coords, coords))) + 4.0;
-    }
-    return get_color(C, i, max_iteration);
+//The pixel's linear color, it can go past 1 with some blend modes
+fn resolve(in: VertexOutput) -> vec4<f32> {
+    let samples = render_samples(in);
+    //Lanczos can leave a tiny or negative total weight
+    let color = samples.weighted.rgb / max(samples.weighted.w, 0.0001);
+    return vec4<f32>(max(color, vec3<f32>(0.0)), samples.alpha);
 }
 
 @fragment
 fn main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var uv = in.uv - vec2<f32>(uniforms.resolution) * 0.5;
-
-
-    var col = vec4<f32>(0.0);
-    let msaa = f32(uniforms.msaa);
+    let color = resolve(in);
+    return encode_output(vec4<f32>(min(color.rgb, vec3<f32>(1.0)), color.a));
+}
 
-    for (var i = 0.0; i < msaa; i += 1.0) {
-        let dxy = vec2<f32>(rand(i * .54321), rand(i * .12345));
-        let c = ((uv + dxy) * vec2<f32>(1.0, -1.0) / uniforms.zoom) - uniforms.position;
-        col += fractal(c);
+//Into a float texture for exporting, left linear and unclamped
+@fragment
+fn float_output(in: VertexOutput) -> @location(0) vec4<f32> {
+    return resolve(in);
+}
+
+fn cached_color(pixel: vec2<i32>) -> vec3<f32> {
+    let clamped = vec2<u32>(clamp(pixel, vec2<i32>(0), vec2<i32>(uniforms.resolution) - 1));
+    let slot = cache_slot(clamped, cache);
+    return get_color(unpack_orbit(textureLoad(cache_a, slot, 0), textureLoad(cache_b, slot, 0))).rgb;
+}
+
+//Whether a pixel and the ones next to it got about the same color from the first sample
+fn is_flat(pixel: vec2<i32>) -> bool {
+    var colors = array<vec3<f32>, 5>(
+        cached_color(pixel),
+        cached_color(pixel + vec2<i32>(1, 0)),
+        cached_color(pixel - vec2<i32>(1, 0)),
+        cached_color(pixel + vec2<i32>(0, 1)),
+        cached_color(pixel - vec2<i32>(0, 1)),
+    );
+    var sum = 0.0;
+    var sum_squared = 0.0;
+    for (var i = 0; i < 5; i += 1) {
+        let b = brightness(colors[i]);
+        sum += b;
+        sum_squared += b * b;
     }
+    let mean = sum / 5.0;
+    return sum_squared / 5.0 - mean * mean < uniforms.adaptive_threshold * uniforms.adaptive_threshold;
+}
 
-    return col / msaa;
+//Progressive passes, the accumulation texture keeps weighted colors with the weights in alpha so
+//it can average them across passes. Samples after the first only go where there are edges, the
+//iteration cache has the first sample of every pixel at this view
+@fragment
+fn accumulate(in: VertexOutput) -> @location(0) vec4<f32> {
+    if uniforms.adaptive_threshold > 0.0 && uniforms.sample > 0u && is_flat(vec2<i32>(in.position.xy)) {
+        discard;
+    }
+    return render_samples(in).weighted;
 }
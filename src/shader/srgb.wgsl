@@ -0,0 +1,28 @@
+//Goes first in every shader that draws onto the screen or into screenshots, after a line setting
+//ENCODE_SRGB. Colors are worked out in linear space, sRGB targets encode them on their own but
+//plain unorm ones need it done here
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    return select(
+        pow((color + 0.055) / 1.055, vec3<f32>(2.4)),
+        color / 12.92,
+        color <= vec3<f32>(0.04045)
+    );
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let clamped = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(
+        1.055 * pow(clamped, vec3<f32>(1.0 / 2.4)) - 0.055,
+        clamped * 12.92,
+        clamped <= vec3<f32>(0.0031308)
+    );
+}
+
+//Takes a linear color and gives what has to be written for it to show up right
+fn encode_output(color: vec4<f32>) -> vec4<f32> {
+    if ENCODE_SRGB {
+        return vec4<f32>(linear_to_srgb(color.rgb), color.a);
+    }
+    return color;
+}
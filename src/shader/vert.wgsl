@@ -6,13 +6,21 @@ struct VertexOutput {
 struct ShaderDataUniforms {
   position: vec2<f32>,
   resolution: vec2<u32>,
-  aspect: vec2<f32>,
+  aspect: f32,
+  //In radians
+  rotation: f32,
   zoom: f32,
-  arr_len: i32,
+  num_layers: u32,
   fractal: u32,
   max_iter: u32,
-  color_num: u32,
   msaa: u32,
+  //Index of the first sample
+  sample: u32,
+  period_tolerance: f32,
+  aa_pattern: u32,
+  aa_filter: u32,
+  aa_samples: u32,
+  adaptive_threshold: f32,
 }
 
 @group(0)
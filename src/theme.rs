@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 use iced_wgpu::Color;
-use iced_winit::widget::button;
+use iced_winit::widget::{button, container};
 
 pub struct Theme {
     pub color: Color,
@@ -15,3 +15,20 @@ impl button::StyleSheet for Theme {
         }
     }
 }
+
+//Dark see-through background so text stays readable on top of the fractal
+pub struct Panel;
+impl container::StyleSheet for Panel {
+    type Style = iced_wgpu::Theme;
+
+    fn appearance(&self, _style: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            text_color: Some(Color::WHITE),
+            background: Some(iced_winit::Background::Color(Color::from_rgba(
+                0.0, 0.0, 0.0, 0.75,
+            ))),
+            border_radius: 5.0,
+            ..Default::default()
+        }
+    }
+}
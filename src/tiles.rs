@@ -0,0 +1,162 @@
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use iced_wgpu::wgpu;
+
+//Side of the squares the picture gets rendered in, same as in compute.wgsl
+pub const TILE_SIZE: u32 = 64;
+
+//How long a frame gets to spend on tiles, the rest of it is for the UI
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+//What one submission should take, short enough to stop close to the budget and to stay well
+//clear of the driver deciding the GPU hung
+const BATCH_TIME: Duration = Duration::from_millis(3);
+
+//Most tiles in one submission, it's the limit on workgroups in one direction
+const MAX_BATCH: u32 = 65535;
+
+//Tiles it takes to cover a picture, they go left to right and then top to bottom
+pub fn count(size: [u32; 2]) -> u32 {
+    size[0].div_ceil(TILE_SIZE) * size[1].div_ceil(TILE_SIZE)
+}
+
+//Rectangles covering a run of tiles as [x, y, width, height], at most a bit of a row, some whole
+//rows and then another bit of a row
+pub fn rects(size: [u32; 2], tiles: Range<u32>) -> Vec<[u32; 4]> {
+    let columns = size[0].div_ceil(TILE_SIZE).max(1);
+    let mut rects = Vec::new();
+    let mut tile = tiles.start;
+    while tile < tiles.end {
+        let (row, column) = (tile / columns, tile % columns);
+        let rows = if column == 0 {
+            (tiles.end - tile) / columns
+        } else {
+            0
+        };
+        let (width, height, next) = if rows > 0 {
+            (columns, rows, tile + rows * columns)
+        } else {
+            let end = tiles.end.min((row + 1) * columns);
+            (end - tile, 1, end)
+        };
+        let (x, y) = (column * TILE_SIZE, row * TILE_SIZE);
+        rects.push([
+            x,
+            y,
+            (width * TILE_SIZE).min(size[0] - x),
+            (height * TILE_SIZE).min(size[1].saturating_sub(y)),
+        ]);
+        tile = next;
+    }
+    rects
+}
+
+//Renders tiles a batch per submission until they're done or the frame is out of time, and
+//learns how many fit in a batch as it goes
+pub struct Scheduler {
+    batch: u32,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self { batch: 16 }
+    }
+}
+
+impl Scheduler {
+    //Returns how far it got
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tiles: Range<u32>,
+        mut render: impl FnMut(&mut wgpu::CommandEncoder, Range<u32>),
+    ) -> u32 {
+        let start = Instant::now();
+        let mut next = tiles.start;
+        while next < tiles.end {
+            let batch = next..tiles.end.min(next + self.batch);
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            render(&mut encoder, batch.clone());
+            let submitted = Instant::now();
+            queue.submit(Some(encoder.finish()));
+            device.poll(wgpu::Maintain::Wait);
+            let took = submitted.elapsed();
+            next = batch.end;
+            if took < BATCH_TIME / 2 {
+                self.batch = (self.batch * 2).min(MAX_BATCH);
+            } else if took > BATCH_TIME * 2 {
+                self.batch = (self.batch / 2).max(1);
+            }
+            //Another batch like that one wouldn't fit
+            if start.elapsed() + took > FRAME_BUDGET {
+                break;
+            }
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Marks every pixel the rectangles cover, panics if one gets covered twice
+    fn coverage(size: [u32; 2], tiles: Range<u32>) -> Vec<bool> {
+        let mut covered = vec![false; (size[0] * size[1]) as usize];
+        for [x, y, width, height] in rects(size, tiles) {
+            assert!(x + width <= size[0] && y + height <= size[1]);
+            for py in y..y + height {
+                for px in x..x + width {
+                    let pixel = &mut covered[(py * size[0] + px) as usize];
+                    assert!(!*pixel, "{px} {py} covered twice");
+                    *pixel = true;
+                }
+            }
+        }
+        covered
+    }
+
+    #[test]
+    fn count_rounds_up() {
+        assert_eq!(count([64, 64]), 1);
+        assert_eq!(count([65, 64]), 2);
+        assert_eq!(count([200, 130]), 4 * 3);
+        assert_eq!(count([0, 100]), 0);
+    }
+
+    #[test]
+    fn rects_cover_everything_once() {
+        let size = [300, 170];
+        assert!(coverage(size, 0..count(size)).into_iter().all(|c| c));
+    }
+
+    #[test]
+    fn rects_in_batches_add_up() {
+        let size = [300, 170];
+        let mut covered = vec![false; (size[0] * size[1]) as usize];
+        for start in (0..count(size)).step_by(3) {
+            let batch = start..(start + 3).min(count(size));
+            for (all, this) in covered.iter_mut().zip(coverage(size, batch)) {
+                assert!(!(*all && this));
+                *all |= this;
+            }
+        }
+        assert!(covered.into_iter().all(|c| c));
+    }
+
+    #[test]
+    fn partial_rows() {
+        //5 tiles to a row, starting half way through the first one and ending in the third
+        let size = [320, 200];
+        assert_eq!(
+            rects(size, 3..12),
+            vec![[192, 0, 128, 64], [0, 64, 320, 64], [0, 128, 128, 64]]
+        );
+        assert!(rects(size, 4..4).is_empty());
+    }
+}